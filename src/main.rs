@@ -11,11 +11,15 @@ fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
 
     let args: Vec<String> = std::env::args().collect();
-    for arg in args {
+    for (i, arg) in args.iter().enumerate() {
         if arg == "bench" {
             search::bench::run_bench();
             return;
         }
+        if arg == "mate" {
+            search::dfpn::run_solver_cli(&args[i + 1..]);
+            return;
+        }
     }
 
     let mut uci_handler = uci::uci_handler::UciHandler::new();
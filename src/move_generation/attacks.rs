@@ -103,3 +103,31 @@ pub fn pawn_double_push(single_pushes: Bitboard, occ: Bitboard, color: Color) ->
         Color::Black => single_pushes.shift(Direction::S, 1) & !occ & Bitboard::RANK_5,
     }
 }
+
+// squares strictly between two squares on the same rank, file or diagonal, empty if they aren't aligned
+pub fn between(a: Square, b: Square) -> Bitboard {
+    let a_bb = a.as_bitboard();
+    let b_bb = b.as_bitboard();
+
+    if rook(a, Bitboard::EMPTY).overlaps(b_bb) {
+        rook(a, b_bb) & rook(b, a_bb)
+    } else if bishop(a, Bitboard::EMPTY).overlaps(b_bb) {
+        bishop(a, b_bb) & bishop(b, a_bb)
+    } else {
+        Bitboard::EMPTY
+    }
+}
+
+// the full rank, file or diagonal through two squares, empty if they aren't aligned
+pub fn line(a: Square, b: Square) -> Bitboard {
+    let a_bb = a.as_bitboard();
+    let b_bb = b.as_bitboard();
+
+    if rook(a, Bitboard::EMPTY).overlaps(b_bb) {
+        rook(a, Bitboard::EMPTY) & rook(b, Bitboard::EMPTY) | a_bb | b_bb
+    } else if bishop(a, Bitboard::EMPTY).overlaps(b_bb) {
+        bishop(a, Bitboard::EMPTY) & bishop(b, Bitboard::EMPTY) | a_bb | b_bb
+    } else {
+        Bitboard::EMPTY
+    }
+}
@@ -603,6 +603,19 @@ pub struct Board {
     pub ep_sq: Option<Square>,
     pub castle_rights: CastleRights,
     pub halfmoves: u16,
+    pub pawn_hash: ZobristHash,
+
+    // attack info, recomputed after every move
+    // squares attacked by the enemy, ignoring whether the attacks are legal
+    pub threats: Bitboard,
+    // enemy pieces giving check to the side to move
+    pub checkers: Bitboard,
+    // pieces of either color that are the only thing between each color's king and an enemy slider
+    pub blockers: [Bitboard; Color::CNT as usize],
+    // enemy sliders pinning one of each color's pieces to its king
+    pub pinners: [Bitboard; Color::CNT as usize],
+    // squares from which each of our piece types would attack the enemy king
+    pub check_squares: [Bitboard; Piece::CNT as usize],
 }
 
 pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0";
@@ -616,6 +629,12 @@ impl Board {
             ep_sq: None,
             castle_rights: CastleRights::new(),
             halfmoves: 0,
+            pawn_hash: ZobristHash::EMPTY,
+            threats: Bitboard::EMPTY,
+            checkers: Bitboard::EMPTY,
+            blockers: [Bitboard::EMPTY; Color::CNT as usize],
+            pinners: [Bitboard::EMPTY; Color::CNT as usize],
+            check_squares: [Bitboard::EMPTY; Piece::CNT as usize],
         }
     }
 
@@ -646,6 +665,13 @@ impl Board {
         self.all[Color::White.as_index()] | self.all[Color::Black.as_index()]
     }
 
+    // whether the side to move has anything besides king and pawns, which makes zugzwang unlikely
+    pub fn has_non_pawn_material(&self) -> bool {
+        let pawns_and_king =
+            self.pieces[Piece::PAWN.as_index()] | self.pieces[Piece::KING.as_index()];
+        self.us().without(pawns_and_king).not_empty()
+    }
+
     pub fn promotable_pawns(&self) -> Bitboard {
         let color = self.stm;
         let pawns = self.piece_bb(Piece::PAWN, color);
@@ -659,8 +685,95 @@ impl Board {
         self.piece_bb(Piece::KING, self.stm).lsb()
     }
 
+    pub fn opp_king_sq(&self) -> Square {
+        self.piece_bb(Piece::KING, self.stm.flip()).lsb()
+    }
+
+    // squares from which a piece of the side to move would attack the enemy king
+    pub fn check_squares(&self, piece: Piece) -> Bitboard {
+        self.check_squares[piece.as_index()]
+    }
+
+    // our pieces that are the only thing standing between one of our sliders and the enemy king,
+    // so moving them off the line gives a discovered check
+    pub fn discovered_check_blockers(&self) -> Bitboard {
+        self.blockers[self.stm.flip().as_index()] & self.us()
+    }
+
+    fn update_attack_info(&mut self) {
+        let stm = self.stm;
+        let occ = self.occupied();
+
+        let king_sq = self.king_sq();
+        let opps = stm.flip();
+        self.checkers = (attacks::knight(king_sq) & self.piece_bb(Piece::KNIGHT, opps))
+            | (attacks::pawn(king_sq, stm) & self.piece_bb(Piece::PAWN, opps))
+            | (attacks::bishop(king_sq, occ) & self.diagonal_sliders(opps))
+            | (attacks::rook(king_sq, occ) & self.orthogonal_sliders(opps));
+
+        for color in Color::LIST {
+            let king_sq = self.piece_bb(Piece::KING, color).lsb();
+            let snipers = (attacks::rook(king_sq, Bitboard::EMPTY)
+                & self.orthogonal_sliders(color.flip()))
+                | (attacks::bishop(king_sq, Bitboard::EMPTY) & self.diagonal_sliders(color.flip()));
+
+            let mut blockers = Bitboard::EMPTY;
+            let mut pinners = Bitboard::EMPTY;
+            bitloop!(|sniper| snipers, {
+                let blocking = attacks::between(sniper, king_sq) & occ;
+                if blocking.popcount() == 1 {
+                    blockers |= blocking;
+                    if blocking.overlaps(self.all[color.as_index()]) {
+                        pinners |= sniper.as_bitboard();
+                    }
+                }
+            });
+
+            self.blockers[color.as_index()] = blockers;
+            self.pinners[color.as_index()] = pinners;
+        }
+
+        let opp_king_sq = self.opp_king_sq();
+        let bishop_checks = attacks::bishop(opp_king_sq, occ);
+        let rook_checks = attacks::rook(opp_king_sq, occ);
+        self.check_squares[Piece::PAWN.as_index()] = attacks::pawn(opp_king_sq, opps);
+        self.check_squares[Piece::KNIGHT.as_index()] = attacks::knight(opp_king_sq);
+        self.check_squares[Piece::BISHOP.as_index()] = bishop_checks;
+        self.check_squares[Piece::ROOK.as_index()] = rook_checks;
+        self.check_squares[Piece::QUEEN.as_index()] = bishop_checks | rook_checks;
+        self.check_squares[Piece::KING.as_index()] = Bitboard::EMPTY;
+
+        self.threats = self.attacked_squares(opps);
+    }
+
+    // every square attacked by one of the pieces of this color
+    fn attacked_squares(&self, color: Color) -> Bitboard {
+        let occ = self.occupied();
+        let mut attacked = attacks::pawn_setwise(self.piece_bb(Piece::PAWN, color), color);
+
+        bitloop!(|sq| self.piece_bb(Piece::KNIGHT, color), {
+            attacked |= attacks::knight(sq);
+        });
+        bitloop!(|sq| self.diagonal_sliders(color), {
+            attacked |= attacks::bishop(sq, occ);
+        });
+        bitloop!(|sq| self.orthogonal_sliders(color), {
+            attacked |= attacks::rook(sq, occ);
+        });
+
+        attacked | attacks::king(self.piece_bb(Piece::KING, color).lsb())
+    }
+
+    pub fn orthogonal_sliders(&self, color: Color) -> Bitboard {
+        self.piece_bb(Piece::ROOK, color) | self.piece_bb(Piece::QUEEN, color)
+    }
+
+    pub fn diagonal_sliders(&self, color: Color) -> Bitboard {
+        self.piece_bb(Piece::BISHOP, color) | self.piece_bb(Piece::QUEEN, color)
+    }
+
     pub fn in_check(&self) -> bool {
-        self.king_sq().is_attacked(self)
+        self.checkers.not_empty()
     }
 
     pub fn can_ks_castle(&self) -> bool {
@@ -674,11 +787,18 @@ impl Board {
     fn toggle(&mut self, mask: Bitboard, piece: Piece, color: Color) {
         self.all[color.as_index()] ^= mask;
         self.pieces[piece.as_index()] ^= mask;
+
+        if piece == Piece::PAWN {
+            bitloop!(|sq| mask, {
+                self.pawn_hash.hash_piece(color, piece, sq);
+            });
+        }
     }
 
     pub fn play_nullmove(&mut self, zobrist_stack: &mut ZobristStack) {
         self.stm = self.stm.flip();
         self.ep_sq = None;
+        self.update_attack_info();
         zobrist_stack.push(ZobristHash::complete(self));
     }
 
@@ -741,7 +861,8 @@ impl Board {
             }
         }
 
-        if self.in_check() {
+        // the check info is stale until the move is done, so we look at our king directly
+        if self.king_sq().is_attacked(self) {
             return false;
         }
 
@@ -752,6 +873,7 @@ impl Board {
         if piece == Piece::PAWN || mv.is_capture() {
             self.halfmoves = 0;
         }
+        self.update_attack_info();
 
         zobrist_stack.push(ZobristHash::complete(self)); // TODO: make this use incremental updates
 
@@ -800,6 +922,8 @@ impl Board {
         board.castle_rights = CastleRights::from_str(castling);
         board.ep_sq = Square::from_string(ep);
         board.halfmoves = halfmoves.parse::<u16>().unwrap();
+        board.pawn_hash = ZobristHash::pawns(&board);
+        board.update_attack_info();
 
         board
     }
@@ -880,7 +1004,10 @@ impl Board {
 
 #[cfg(test)]
 mod tests {
-    use crate::{move_generation::board_rep::Board, move_generation::perft};
+    use crate::{
+        move_generation::board_rep::{Board, Square},
+        move_generation::perft,
+    };
 
     #[test]
     fn fen_test() {
@@ -890,4 +1017,21 @@ mod tests {
             assert_eq!(Board::from_fen(fen).as_fen(), fen);
         }
     }
+
+    #[test]
+    fn threats_match_attacked_squares() {
+        for pos in perft::test_postions() {
+            let board = Board::from_fen(pos.fen);
+            for i in 0..Square::CNT {
+                let sq = Square::new(i);
+                assert_eq!(
+                    board.threats.overlaps(sq.as_bitboard()),
+                    sq.is_attacked(&board),
+                    "\nFen: {}\nSquare: {}",
+                    pos.fen,
+                    sq.as_string(),
+                );
+            }
+        }
+    }
 }
@@ -275,6 +275,62 @@ impl Move {
             }
         }
     }
+
+    // Whether this (legal) move checks the enemy king, using the check info cached on the board.
+    pub fn gives_check(self, board: &Board) -> bool {
+        let from = self.from();
+        let to = self.to();
+        let opp_king_sq = board.opp_king_sq();
+        let piece = board.piece_on_sq(from);
+
+        // direct check
+        if board.check_squares(piece).overlaps(to.as_bitboard()) {
+            return true;
+        }
+
+        // discovered check, unless we stay on the line between the slider and the king
+        if board
+            .discovered_check_blockers()
+            .overlaps(from.as_bitboard())
+            && !attacks::line(from, opp_king_sq).overlaps(to.as_bitboard())
+        {
+            return true;
+        }
+
+        match self.flag() {
+            Flag::NONE | Flag::CAPTURE | Flag::DOUBLE_PUSH => false,
+            Flag::EP => {
+                // removing two pawns from the board can uncover a slider that no blocker covers
+                let captured_sq = to.row_swap();
+                let occ = (board.occupied() ^ from.as_bitboard() ^ captured_sq.as_bitboard())
+                    | to.as_bitboard();
+                let stm = board.stm;
+                (attacks::bishop(opp_king_sq, occ) & board.diagonal_sliders(stm)).not_empty()
+                    || (attacks::rook(opp_king_sq, occ) & board.orthogonal_sliders(stm)).not_empty()
+            }
+            Flag::KS_CASTLE | Flag::QS_CASTLE => {
+                // only the rook can give check, from the square the king skipped over
+                let rook_to = if self.flag() == Flag::KS_CASTLE {
+                    from.right(1)
+                } else {
+                    from.left(1)
+                };
+                let occ = board.occupied() ^ from.as_bitboard() | to.as_bitboard();
+                attacks::rook(rook_to, occ).overlaps(opp_king_sq.as_bitboard())
+            }
+            _ => {
+                // promotion, the new piece attacks through the square the pawn left
+                let occ = board.occupied() ^ from.as_bitboard() | to.as_bitboard();
+                let attacks = match self.promo_piece() {
+                    Piece::KNIGHT => attacks::knight(to),
+                    Piece::BISHOP => attacks::bishop(to, occ),
+                    Piece::ROOK => attacks::rook(to, occ),
+                    _ => attacks::queen(to, occ),
+                };
+                attacks.overlaps(opp_king_sq.as_bitboard())
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -341,4 +397,41 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn gives_check_matches_playing_the_move() {
+        let mut fens: Vec<&str> = test_postions().iter().map(|pos| pos.fen).collect();
+        fens.extend([
+            // discovered checks through en passant
+            "8/8/8/K2pP2q/8/8/8/7k w - d6 0 1",
+            "8/8/8/k2Pp2Q/8/8/8/7K b - d3 0 1",
+            "7k/8/8/3pP3/8/8/8/B6K w - d6 0 1",
+            // castling checks
+            "5k2/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            "3k4/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            // promotions, including ones uncovering a check
+            "1n1n1k2/2P5/8/8/8/8/8/B6K w - - 0 1",
+            "k7/4P3/8/8/8/8/8/4R2K w - - 0 1",
+            "3r4/kP6/8/8/8/8/8/7K w - - 0 1",
+        ]);
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            let mut picker = MovePicker::new();
+            while let Some(mv) = picker.simple_pick::<true>(&board) {
+                let mut new_board = board.clone();
+                if !new_board.simple_try_play(mv) {
+                    continue;
+                }
+
+                assert_eq!(
+                    new_board.in_check(),
+                    mv.gives_check(&board),
+                    "\nFen: {}\nMove: {}",
+                    fen,
+                    mv.as_string(),
+                );
+            }
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use arrayvec::ArrayVec;
+
 use crate::{
     bitloop,
     move_generation::{
@@ -5,7 +7,10 @@ use crate::{
         board_rep::{Bitboard, Board, Piece, Square},
         chess_move::{Flag, Move},
     },
-    search::history::History,
+    search::{
+        history::{CaptureHistory, History, NodeHistory},
+        static_exchange::SEE_VALS,
+    },
     tuple_constants_enum,
 };
 
@@ -18,33 +23,10 @@ macro_rules! into_moves {
     }};
 }
 
-const MVV_LVA: [[i16; (Piece::CNT + 1) as usize]; (Piece::CNT + 1) as usize] = {
-    // knight, bishop, rook, queen, pawn, king, none (for en passant)
-    let scores: [i16; (Piece::CNT + 1) as usize] = [3, 4, 5, 9, 1, 0, 1];
-    let mut result: [[i16; (Piece::CNT + 1) as usize]; (Piece::CNT + 1) as usize] =
-        [[0; (Piece::CNT + 1) as usize]; (Piece::CNT + 1) as usize];
-
-    let mut a = 0;
-    while a < (Piece::CNT + 1) as usize {
-        let mut v = 0;
-        while v < (Piece::CNT + 1) as usize {
-            result[a][v] = scores[v] - scores[a];
-            v += 1;
-        }
-        a += 1;
-    }
-
-    result
-};
-
-const fn mvv_lva(attacker: Piece, victim: Piece) -> i16 {
-    MVV_LVA[attacker.as_index()][victim.as_index()]
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct ScoredMove {
     mv: Move,
-    score: i16,
+    score: i32,
 }
 
 impl ScoredMove {
@@ -67,7 +49,10 @@ impl MoveStage {
         START,
         TT_MOVE,
         NOISY,
-        KILLER,
+        QUIET_CHECKS,
+        KILLER1,
+        KILLER2,
+        COUNTER,
         QUIET_AND_BAD_CAP
     );
 
@@ -86,10 +71,17 @@ pub struct MovePicker {
     idx: usize,
     limit: usize,
     bad_cap_cnt: usize,
+    skip_quiets: bool,
+    quiet_checks: bool,
+    // moves we must not return from the generated list, because they were already returned
+    // by an earlier stage or are excluded
+    skip_list: ArrayVec<Move, { Self::SKIP_LIST_SIZE }>,
 }
 
 impl MovePicker {
     pub const SIZE: usize = u8::MAX as usize;
+    // excluded move, TT move, 2 killers and the counter move
+    const SKIP_LIST_SIZE: usize = 5;
 
     pub fn new() -> Self {
         Self {
@@ -98,7 +90,40 @@ impl MovePicker {
             idx: 0,
             limit: 0,
             bad_cap_cnt: 0,
+            skip_quiets: false,
+            quiet_checks: false,
+            skip_list: ArrayVec::new(),
+        }
+    }
+
+    // The excluded move is never returned, used by singular extensions to search every move except the TT move.
+    pub fn exclude(&mut self, mv: Move) {
+        if !mv.is_null() {
+            self.skip_list.push(mv);
+        }
+    }
+
+    // Once the search decides the remaining quiets aren't worth trying,
+    // we stop generating and returning them.
+    pub fn skip_quiets(&mut self) {
+        self.skip_quiets = true;
+    }
+
+    // Without quiets we normally stop after the noisy moves,
+    // this makes us follow them up with quiet moves that give check.
+    pub fn include_quiet_checks(&mut self) {
+        self.quiet_checks = true;
+    }
+
+    // Moves we return before generating the full list. We remember them,
+    // so they don't get returned again by a later stage or from the generated list.
+    fn try_special_move(&mut self, mv: Move, board: &Board) -> Option<Move> {
+        if mv.is_null() || self.skip_list.contains(&mv) || !mv.is_pseudolegal(board) {
+            return None;
         }
+
+        self.skip_list.push(mv);
+        Some(mv)
     }
 
     fn add(&mut self, mv: Move) {
@@ -239,7 +264,13 @@ impl MovePicker {
         mv
     }
 
-    fn score_noisy_moves(&mut self, board: &Board) {
+    fn score_noisy_moves(&mut self, board: &Board, history: &NodeHistory) {
+        const MVV_SCALE: i32 = 16;
+        const CAP_HIST_DIVISOR: i32 = 4;
+        // captures with a good history get some leeway in the SEE check that decides if they are good or bad
+        const CAP_HIST_SEE_THRESHOLD: bool = true;
+        const CAP_HIST_SEE_DIVISOR: i32 = 64;
+
         // TODO: give bonus to promotions
         let mut start = self.idx as i32;
         let mut end = self.limit as i32 - 1;
@@ -250,12 +281,24 @@ impl MovePicker {
 
             let attacker = board.piece_on_sq(mv.from());
             let victim = board.piece_on_sq(mv.to());
-            self.list[i].score = mvv_lva(attacker, victim);
+            let victim_value = if mv.flag() == Flag::EP {
+                SEE_VALS[Piece::PAWN.as_index()]
+            } else {
+                SEE_VALS[victim.as_index()]
+            };
+            let cap_hist_score = history.capture_score(board, mv);
+            self.list[i].score = MVV_SCALE * victim_value + cap_hist_score / CAP_HIST_DIVISOR;
+
+            let see_threshold = if CAP_HIST_SEE_THRESHOLD {
+                -cap_hist_score / CAP_HIST_SEE_DIVISOR
+            } else {
+                0
+            };
 
             // what we are doing here is ordering the good captures at the start of the list,
             // and bad at the end. That way we can store the bad captures in the list
             // and pull them back out in the QUIET_AND_BAD_CAP stage.
-            if board.see(mv, attacker, victim, 0) {
+            if board.see(mv, attacker, victim, see_threshold) {
                 // good capture
                 start += 1;
             } else {
@@ -269,19 +312,51 @@ impl MovePicker {
         self.limit -= self.bad_cap_cnt; // remove bad captures from this stage (save for later)
     }
 
-    fn score_quiet_moves(&mut self, board: &Board, history: &History, quiet_start: usize) {
+    // Replaces everything after the noisy moves with the quiet checks.
+    // Bad captures get overwritten, but we never return those when we aren't using quiets anyways.
+    fn gen_quiet_checks(&mut self, board: &Board, history: &NodeHistory) {
+        // discovered checks can't be blocked, so they are more likely to be good
+        const DISCOVERED_CHECK_BONUS: i32 = 1 << 16;
+
+        let start = self.limit;
+        self.gen_moves::<false>(board);
+
+        let blockers = board.discovered_check_blockers();
+        let mut i = start;
+        while i < self.limit {
+            let mv = self.list[i].mv;
+            let is_castle = mv.flag() == Flag::KS_CASTLE || mv.flag() == Flag::QS_CASTLE;
+
+            if !mv.is_promo() && !is_castle && mv.gives_check(board) {
+                let discovered = blockers.overlaps(mv.from().as_bitboard());
+                self.list[i].score = history.quiet_score(board, mv)
+                    + if discovered {
+                        DISCOVERED_CHECK_BONUS
+                    } else {
+                        0
+                    };
+                i += 1;
+            } else {
+                self.limit -= 1;
+                self.list.swap(i, self.limit);
+            }
+        }
+    }
+
+    fn score_quiet_moves(&mut self, board: &Board, history: &NodeHistory, quiet_start: usize) {
         for elem in self.list[quiet_start..self.limit].iter_mut() {
             debug_assert!(elem.mv.is_quiet());
-            elem.score = history.score(board, elem.mv) as i16;
+            elem.score = history.quiet_score(board, elem.mv);
         }
     }
 
     pub fn pick<const INCLUDE_QUIETS: bool>(
         &mut self,
         board: &Board,
-        history: &History,
+        history: &NodeHistory,
         tt_move: Move,
-        killer: Move,
+        killers: [Move; 2],
+        counter: Move,
     ) -> Option<Move> {
         loop {
             while self.stage_complete() {
@@ -289,22 +364,40 @@ impl MovePicker {
 
                 match self.stage {
                     MoveStage::TT_MOVE => {
-                        if tt_move.is_pseudolegal(board) {
-                            return Some(tt_move);
+                        if let Some(mv) = self.try_special_move(tt_move, board) {
+                            return Some(mv);
                         }
                     }
                     MoveStage::NOISY => {
                         self.gen_moves::<true>(board);
-                        self.score_noisy_moves(board);
+                        self.score_noisy_moves(board, history);
+                    }
+                    MoveStage::QUIET_CHECKS => {
+                        // with quiets, checks are generated together with the rest of them later
+                        if !INCLUDE_QUIETS {
+                            if !self.quiet_checks {
+                                return None;
+                            }
+
+                            self.gen_quiet_checks(board, history);
+                        }
                     }
-                    MoveStage::KILLER => {
+                    MoveStage::KILLER1 | MoveStage::KILLER2 | MoveStage::COUNTER => {
                         // Stop after noisy stage if we aren't using quiets
                         if !INCLUDE_QUIETS {
                             return None;
                         }
 
-                        if killer.is_pseudolegal(board) {
-                            return Some(killer);
+                        if !self.skip_quiets {
+                            let mv = match self.stage {
+                                MoveStage::KILLER1 => killers[0],
+                                MoveStage::KILLER2 => killers[1],
+                                _ => counter,
+                            };
+
+                            if let Some(mv) = self.try_special_move(mv, board) {
+                                return Some(mv);
+                            }
                         }
                     }
                     MoveStage::QUIET_AND_BAD_CAP => {
@@ -316,37 +409,42 @@ impl MovePicker {
                         // we will be scoring captures as quiets
                         let quiet_start = self.limit;
 
-                        self.gen_moves::<false>(board);
-                        self.score_quiet_moves(board, history, quiet_start);
+                        if !self.skip_quiets {
+                            self.gen_moves::<false>(board);
+                            self.score_quiet_moves(board, history, quiet_start);
+                        }
                     }
                     _ => return None,
                 }
             }
 
             let potential_move = self.next_best_move();
-            /*
-                TODO: can I make this more efficient?
-                tt_move and killer could be null, or they could
-                just straight up not apply to the position
-                ie. if we are in noisy stage and killer is quiet
-
-                maybe I could store a list of potential repeats in the picker struct to modify as I go...
-            */
-            if ![tt_move, killer].contains(&potential_move) {
+            if self.skip_quiets && potential_move.is_quiet() {
+                continue;
+            }
+
+            if !self.skip_list.contains(&potential_move) {
                 return Some(potential_move);
             }
         }
     }
 
     pub fn simple_pick<const INCLUDE_QUIETS: bool>(&mut self, board: &Board) -> Option<Move> {
-        // NOTE: compiler should optimize dummy_hist out I think... I should check sometime
-        let dummy_hist = History::new();
-        self.pick::<INCLUDE_QUIETS>(board, &dummy_hist, Move::NULL, Move::NULL)
+        static EMPTY_HISTORY: History = History::new();
+        static EMPTY_CAPTURE_HISTORY: CaptureHistory = CaptureHistory::new();
+
+        let history = NodeHistory::new(&EMPTY_HISTORY, &EMPTY_CAPTURE_HISTORY, [None, None]);
+        self.pick::<INCLUDE_QUIETS>(board, &history, Move::NULL, [Move::NULL; 2], Move::NULL)
     }
 
     pub fn first_legal_mv(board: &Board) -> Option<Move> {
         let mut generator = Self::new();
-        generator.simple_pick::<true>(board)
+        while let Some(mv) = generator.simple_pick::<true>(board) {
+            if board.clone().simple_try_play(mv) {
+                return Some(mv);
+            }
+        }
+        None
     }
 }
 
@@ -440,4 +538,88 @@ mod tests {
         }
         assert_eq!(expected_count, actual);
     }
+
+    #[test]
+    fn generates_quiet_checks() {
+        use super::*;
+
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/8/8/8/4N3/8/4R3/4K3 w - - 0 1",
+            "7k/8/8/8/3P4/8/1B6/K7 w - - 0 1",
+            "8/8/3k4/8/2P5/8/8/4K3 w - - 0 1",
+            "3k4/8/8/2N5/8/8/1Q6/4K1R1 w - - 0 1",
+        ];
+
+        let history = History::new();
+        let capture_history = CaptureHistory::new();
+        let node_history = NodeHistory::new(&history, &capture_history, [None, None]);
+        let is_legal = |board: &Board, mv: Move| board.clone().simple_try_play(mv);
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+
+            let mut expected = vec![];
+            let mut g = MovePicker::new();
+            while let Some(mv) = g.simple_pick::<true>(&board) {
+                let is_castle = mv.flag() == Flag::KS_CASTLE || mv.flag() == Flag::QS_CASTLE;
+                if !mv.is_quiet() || mv.is_promo() || is_castle {
+                    continue;
+                }
+
+                let mut next = board.clone();
+                if next.simple_try_play(mv) && next.in_check() {
+                    expected.push(mv);
+                }
+            }
+
+            let mut actual = vec![];
+            let mut g = MovePicker::new();
+            g.include_quiet_checks();
+            while let Some(mv) = g.pick::<false>(
+                &board,
+                &node_history,
+                Move::NULL,
+                [Move::NULL; 2],
+                Move::NULL,
+            ) {
+                if mv.is_quiet() && is_legal(&board, mv) {
+                    actual.push(mv);
+                }
+            }
+
+            assert_eq!(expected.len(), actual.len(), "{fen}");
+            for mv in expected {
+                assert!(actual.contains(&mv), "{fen}: missing {}", mv.as_string());
+            }
+        }
+    }
+
+    #[test]
+    fn special_moves_are_not_repeated() {
+        use super::*;
+        use crate::move_generation::board_rep::START_FEN;
+
+        let board = Board::from_fen(START_FEN);
+        let tt_move = Move::from_str("e2e4", &board).unwrap();
+        let killers = [tt_move, Move::from_str("g1f3", &board).unwrap()];
+        let counter = killers[1];
+        let excluded = Move::from_str("d2d4", &board).unwrap();
+
+        let history = History::new();
+        let capture_history = CaptureHistory::new();
+        let node_history = NodeHistory::new(&history, &capture_history, [None, None]);
+
+        let mut list = vec![];
+        let mut g = MovePicker::new();
+        g.exclude(excluded);
+        while let Some(mv) = g.pick::<true>(&board, &node_history, tt_move, killers, counter) {
+            assert!(!list.contains(&mv), "{} is duplicate", mv.as_string());
+            list.push(mv);
+        }
+
+        assert_eq!(list.len(), 19);
+        assert_eq!(list[..2], killers);
+        assert!(!list.contains(&excluded));
+    }
 }
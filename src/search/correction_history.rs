@@ -0,0 +1,48 @@
+use crate::{
+    move_generation::board_rep::{Board, Color},
+    search::constants::{Depth, EvalScore, MATE_THRESHOLD},
+};
+
+// Static eval errors tend to be similar across positions with the same pawn structure,
+// so we learn the average difference between search score and raw eval per pawn key,
+// and use it to correct the eval we base our pruning decisions on.
+#[derive(Debug, Clone)]
+pub struct CorrectionHistory {
+    pawn: Vec<[EvalScore; Color::CNT as usize]>,
+}
+
+impl CorrectionHistory {
+    const SIZE: usize = 16384;
+    // corrections are stored with extra precision so small updates aren't rounded away
+    const GRAIN: i32 = 256;
+    const WEIGHT_SCALE: i32 = 256;
+    const MAX_WEIGHT: i32 = 128;
+    const MAX: i32 = Self::GRAIN * 32;
+
+    pub fn new() -> Self {
+        Self {
+            pawn: vec![[0; Color::CNT as usize]; Self::SIZE],
+        }
+    }
+
+    fn pawn_entry(&self, board: &Board) -> EvalScore {
+        self.pawn[board.pawn_hash.as_usize() % Self::SIZE][board.stm.as_index()]
+    }
+
+    pub fn correct(&self, board: &Board, raw_eval: EvalScore) -> EvalScore {
+        let corrected = raw_eval + self.pawn_entry(board) / Self::GRAIN;
+        corrected.clamp(-MATE_THRESHOLD + 1, MATE_THRESHOLD - 1)
+    }
+
+    // moves the correction towards the error we just observed, trusting deeper searches more
+    pub fn update(&mut self, board: &Board, depth: Depth, score: EvalScore, raw_eval: EvalScore) {
+        let d = i32::from(depth);
+        let weight = (d * d + 2 * d + 1).min(Self::MAX_WEIGHT);
+        let error = (score - raw_eval) * Self::GRAIN;
+
+        let entry = &mut self.pawn[board.pawn_hash.as_usize() % Self::SIZE][board.stm.as_index()];
+        let blended =
+            (*entry * (Self::WEIGHT_SCALE - weight) + error * weight) / Self::WEIGHT_SCALE;
+        *entry = blended.clamp(-Self::MAX, Self::MAX);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{
+    move_generation::{
+        board_rep::{Color, Piece, Square},
+        chess_move::Move,
+    },
+    search::history::PieceTo,
+};
+
+// The quiet move that last refuted the opponent's previous move,
+// indexed by [side to move][opponent's piece][opponent's to square].
+#[derive(Debug, Clone)]
+pub struct CounterMoves {
+    moves: [[[Move; Square::CNT as usize]; Piece::CNT as usize]; Color::CNT as usize],
+}
+
+impl CounterMoves {
+    pub const fn new() -> Self {
+        Self {
+            moves: [[[Move::NULL; Square::CNT as usize]; Piece::CNT as usize]; Color::CNT as usize],
+        }
+    }
+
+    pub fn update(&mut self, color: Color, prev: PieceTo, mv: Move) {
+        self.moves[color.as_index()][prev.piece.as_index()][prev.to.as_index()] = mv;
+    }
+
+    pub fn counter(&self, color: Color, prev: PieceTo) -> Move {
+        self.moves[color.as_index()][prev.piece.as_index()][prev.to.as_index()]
+    }
+}
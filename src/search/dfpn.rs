@@ -0,0 +1,599 @@
+use crate::{
+    move_generation::{
+        board_rep::{Board, START_FEN},
+        chess_move::Move,
+        movegen::MovePicker,
+    },
+    search::{
+        constants::{Nodes, Ply},
+        search_manager::stop_flag_is_set,
+        zobrist::ZobristHash,
+        zobrist_stack::ZobristStack,
+    },
+    uci::setoption::Hash,
+};
+
+// Depth-first proof-number search (df-pn) for forced mates.
+// Every node has a proof number (how many leaves we still need to prove to show the attacker mates)
+// and a disproof number (the same for showing the defender escapes). We store them from the point of view
+// of the side to move: phi is the number for the side to move winning, delta the one for it losing, so
+// for every node phi = min(delta of the children) and delta = sum(phi of the children).
+// We then keep expanding the most proving child until the root is either proven or disproven.
+
+const INF: u32 = u32::MAX / 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Entry {
+    phi: u32,
+    delta: u32,
+    // how many plies until mate, and the move that keeps it that far away. Only meaningful once the node is proven
+    mate_plies: Ply,
+    best_mv: Move,
+}
+
+impl Entry {
+    const UNKNOWN: Self = Self::new(1, 1);
+    // the side to move has won, or lost
+    const WIN: Self = Self::new(0, INF);
+    const LOSS: Self = Self::new(INF, 0);
+
+    const fn new(phi: u32, delta: u32) -> Self {
+        Self {
+            phi,
+            delta,
+            mate_plies: 0,
+            best_mv: Move::NULL,
+        }
+    }
+
+    // whether this node has been proven or disproven
+    const fn is_solved(self) -> bool {
+        self.phi == 0 || self.delta == 0
+    }
+
+    // whether the attacker has proven a mate from this node
+    const fn is_proven(self, attacker: bool) -> bool {
+        if attacker {
+            self.phi == 0
+        } else {
+            self.delta == 0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MateResult {
+    // the mating line, starting with the attacker's first move
+    Mate(Vec<Move>),
+    NoMate,
+    // we ran out of nodes, or got told to stop
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Slot {
+    key: u64,
+    entry: Entry,
+    // how many nodes it took to get this entry, zero for empty slots
+    work: u32,
+}
+
+// The proof and disproof numbers depend on how many plies the attacker has left,
+// so every entry is keyed by both the position and that ply budget.
+// The table has a fixed size, so once a bucket is full we overwrite the entry that was cheapest to find.
+#[derive(Debug)]
+struct ProofTable {
+    buckets: Vec<[Slot; Self::BUCKET_SIZE]>,
+}
+
+impl ProofTable {
+    const BUCKET_SIZE: usize = 4;
+
+    fn new(megabytes: usize) -> Self {
+        const BYTES_PER_MB: usize = 1024 * 1024;
+
+        let empty = Slot {
+            key: 0,
+            entry: Entry::UNKNOWN,
+            work: 0,
+        };
+        let len = (megabytes * BYTES_PER_MB / size_of::<[Slot; Self::BUCKET_SIZE]>()).max(1);
+        Self {
+            buckets: vec![[empty; Self::BUCKET_SIZE]; len],
+        }
+    }
+
+    fn key(hash: ZobristHash, plies_left: Ply) -> u64 {
+        hash.as_u64() ^ u64::from(plies_left).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.buckets.len() as u64) as usize
+    }
+
+    fn get(&self, hash: ZobristHash, plies_left: Ply) -> Option<Entry> {
+        let key = Self::key(hash, plies_left);
+        self.buckets[self.index(key)]
+            .iter()
+            .find(|slot| slot.work > 0 && slot.key == key)
+            .map(|slot| slot.entry)
+    }
+
+    fn store(&mut self, hash: ZobristHash, plies_left: Ply, entry: Entry, work: u32) {
+        let key = Self::key(hash, plies_left);
+        let idx = self.index(key);
+        let bucket = &mut self.buckets[idx];
+
+        let slot = match bucket
+            .iter()
+            .position(|slot| slot.work > 0 && slot.key == key)
+        {
+            Some(same) => &mut bucket[same],
+            None => bucket.iter_mut().min_by_key(|slot| slot.work).unwrap(),
+        };
+        *slot = Slot {
+            key,
+            entry,
+            work: work.max(1),
+        };
+    }
+}
+
+struct Child {
+    mv: Move,
+    board: Board,
+    hash: ZobristHash,
+    entry: Entry,
+}
+
+#[derive(Debug)]
+pub struct DfpnSolver {
+    table: ProofTable,
+    nodes: Nodes,
+    // how many repetitions we have run into, so a node can tell whether its subtree saw one
+    repetitions: u64,
+    node_limit: Nodes,
+    // only the UCI solver can be stopped, everyone else just has the node limit
+    interruptible: bool,
+    aborted: bool,
+}
+
+impl DfpnSolver {
+    pub const DEFAULT_NODE_LIMIT: Nodes = 20_000_000;
+
+    pub fn new(megabytes: usize, node_limit: Nodes, interruptible: bool) -> Self {
+        Self {
+            table: ProofTable::new(megabytes),
+            nodes: 0,
+            repetitions: 0,
+            node_limit,
+            interruptible,
+            aborted: false,
+        }
+    }
+
+    pub const fn nodes(&self) -> Nodes {
+        self.nodes
+    }
+
+    // Looks for a mate in at most `max_moves` moves for the side to move.
+    // We try every mate length in turn, so the first proof we find is also the shortest mate.
+    pub fn solve(
+        &mut self,
+        board: &Board,
+        zobrist_stack: &ZobristStack,
+        max_moves: u32,
+    ) -> MateResult {
+        let max_moves = max_moves.min(u32::from(Ply::MAX / 2));
+        let mut zobrist_stack = zobrist_stack.clone();
+        self.nodes = 0;
+        self.repetitions = 0;
+        self.aborted = false;
+
+        for moves in 1..=max_moves {
+            let plies = (2 * moves - 1) as Ply;
+            let root = self.mid(board, &mut zobrist_stack, true, plies, INF, INF);
+
+            if self.aborted {
+                return MateResult::Unknown;
+            }
+            if root.is_proven(true) {
+                let line = self.mating_line(board, &mut zobrist_stack, plies);
+                return if self.aborted {
+                    MateResult::Unknown
+                } else {
+                    MateResult::Mate(line)
+                };
+            }
+        }
+
+        MateResult::NoMate
+    }
+
+    // the result of nodes that need no search, from the point of view of the side to move
+    fn terminal(board: &Board, attacker: bool, plies_left: Ply, has_moves: bool) -> Option<Entry> {
+        if !has_moves {
+            // checkmate is a loss for whoever is to move, stalemate a loss for the attacker
+            return Some(if board.in_check() || attacker {
+                Entry::LOSS
+            } else {
+                Entry::WIN
+            });
+        }
+
+        // the attacker has run out of moves to mate with
+        if plies_left == 0 {
+            return Some(if attacker { Entry::LOSS } else { Entry::WIN });
+        }
+
+        None
+    }
+
+    fn children(
+        &self,
+        board: &Board,
+        zobrist_stack: &mut ZobristStack,
+        plies_left: Ply,
+    ) -> Vec<Child> {
+        let mut children = vec![];
+        let mut picker = MovePicker::new();
+        while let Some(mv) = picker.simple_pick::<true>(board) {
+            let mut new_board = board.clone();
+            if !new_board.try_play_move(mv, zobrist_stack) {
+                continue;
+            }
+            let hash = zobrist_stack.current_hash();
+            zobrist_stack.pop();
+
+            let entry = self
+                .table
+                .get(hash, plies_left - 1)
+                .unwrap_or(Entry::UNKNOWN);
+            children.push(Child {
+                mv,
+                board: new_board,
+                hash,
+                entry,
+            });
+        }
+
+        children
+    }
+
+    fn work_since(&self, start_nodes: Nodes) -> u32 {
+        u32::try_from(self.nodes - start_nodes + 1).unwrap_or(u32::MAX)
+    }
+
+    // Multiple iterative deepening: searches this node until its phi reaches phi_th or its delta reaches delta_th.
+    fn mid(
+        &mut self,
+        board: &Board,
+        zobrist_stack: &mut ZobristStack,
+        attacker: bool,
+        plies_left: Ply,
+        phi_th: u32,
+        delta_th: u32,
+    ) -> Entry {
+        self.nodes += 1;
+        let start_nodes = self.nodes;
+        if self.nodes >= self.node_limit || (self.interruptible && stop_flag_is_set()) {
+            self.aborted = true;
+            return Entry::UNKNOWN;
+        }
+
+        let hash = zobrist_stack.current_hash();
+        let start_repetitions = self.repetitions;
+
+        // a repetition is a draw, which means the attacker failed. Draws depend on the path
+        // we took to get here, so we don't store them, or any disproof they might have led to.
+        if zobrist_stack.twofold_repetition(board.halfmoves) {
+            self.repetitions += 1;
+            return if attacker { Entry::LOSS } else { Entry::WIN };
+        }
+
+        let mut children = if plies_left == 0 {
+            vec![]
+        } else {
+            self.children(board, zobrist_stack, plies_left)
+        };
+        let has_moves = !children.is_empty()
+            || (plies_left == 0 && MovePicker::first_legal_mv(board).is_some());
+
+        if let Some(entry) = Self::terminal(board, attacker, plies_left, has_moves) {
+            self.table
+                .store(hash, plies_left, entry, self.work_since(start_nodes));
+            return entry;
+        }
+
+        loop {
+            let mut entry = Entry::new(
+                children.iter().map(|c| c.entry.delta).min().unwrap_or(INF),
+                children
+                    .iter()
+                    .fold(0, |sum, c| (sum + c.entry.phi).min(INF)),
+            );
+
+            if entry.is_proven(attacker) {
+                // the attacker takes the quickest mate, the defender holds out as long as it can
+                let proven = children.iter().filter(|c| c.entry.is_proven(!attacker));
+                let best = if attacker {
+                    proven.min_by_key(|c| c.entry.mate_plies)
+                } else {
+                    proven.max_by_key(|c| c.entry.mate_plies)
+                };
+                if let Some(best) = best {
+                    entry.mate_plies = 1 + best.entry.mate_plies;
+                    entry.best_mv = best.mv;
+                }
+            }
+
+            if entry.phi >= phi_th || entry.delta >= delta_th || self.aborted {
+                // a draw only ever helps the defender, so proofs and unsolved numbers still hold on other paths
+                let path_dependent = self.repetitions != start_repetitions
+                    && entry.is_solved()
+                    && !entry.is_proven(attacker);
+                if !path_dependent {
+                    self.table
+                        .store(hash, plies_left, entry, self.work_since(start_nodes));
+                }
+                return entry;
+            }
+
+            // expand the child with the smallest delta, and give up on it as soon as
+            // it stops being better than the second best child
+            let mut best = 0;
+            let mut second_delta = INF;
+            for (i, child) in children.iter().enumerate().skip(1) {
+                if child.entry.delta < children[best].entry.delta {
+                    second_delta = children[best].entry.delta;
+                    best = i;
+                } else if child.entry.delta < second_delta {
+                    second_delta = child.entry.delta;
+                }
+            }
+
+            let child = &children[best];
+            let child_phi_th = (delta_th - entry.delta + child.entry.phi).min(INF);
+            let child_delta_th = phi_th.min(second_delta.saturating_add(1));
+
+            zobrist_stack.push(child.hash);
+            let child_board = child.board.clone();
+            let result = self.mid(
+                &child_board,
+                zobrist_stack,
+                !attacker,
+                plies_left - 1,
+                child_phi_th,
+                child_delta_th,
+            );
+            zobrist_stack.pop();
+
+            children[best].entry = result;
+        }
+    }
+
+    // Searches a node until it is proven or disproven, unless the table already knows the answer.
+    fn solve_node(
+        &mut self,
+        board: &Board,
+        zobrist_stack: &mut ZobristStack,
+        attacker: bool,
+        plies_left: Ply,
+    ) -> Entry {
+        match self.table.get(zobrist_stack.current_hash(), plies_left) {
+            Some(entry) if entry.is_solved() => entry,
+            _ => self.mid(board, zobrist_stack, attacker, plies_left, INF, INF),
+        }
+    }
+
+    // Every reply of a proven defender node gets mated in time, but we want one that can't be mated
+    // any sooner, so the line stays as long as the mate we proved.
+    fn slowest_defence(
+        &mut self,
+        board: &Board,
+        zobrist_stack: &mut ZobristStack,
+        plies_left: Ply,
+        best_mv: Move,
+    ) -> Move {
+        let mut children = self.children(board, zobrist_stack, plies_left);
+        // the move the proof picked is the most likely to hold out
+        children.sort_by_key(|child| child.mv != best_mv);
+
+        for child in children {
+            zobrist_stack.push(child.hash);
+            let entry = self.solve_node(&child.board, zobrist_stack, true, plies_left - 3);
+            zobrist_stack.pop();
+
+            if self.aborted {
+                break;
+            }
+            if !entry.is_proven(true) {
+                return child.mv;
+            }
+        }
+
+        best_mv
+    }
+
+    // Follows the best moves of a proven tree. We already know there is no quicker mate from the root,
+    // so any mating move keeps the mate exactly as far away, and the defender has to find the slowest loss.
+    // The table may have overwritten parts of the proof, so we solve those nodes again when we get to them.
+    fn mating_line(
+        &mut self,
+        board: &Board,
+        zobrist_stack: &mut ZobristStack,
+        plies: Ply,
+    ) -> Vec<Move> {
+        let mut line = vec![];
+        let mut board = board.clone();
+        let mut attacker = true;
+
+        for plies_left in (1..=plies).rev() {
+            let entry = self.solve_node(&board, zobrist_stack, attacker, plies_left);
+            if self.aborted || entry.best_mv.is_null() {
+                break;
+            }
+
+            let mv = if !attacker && plies_left >= 3 {
+                self.slowest_defence(&board, zobrist_stack, plies_left, entry.best_mv)
+            } else {
+                entry.best_mv
+            };
+            if self.aborted {
+                break;
+            }
+
+            assert!(board.try_play_move(mv, zobrist_stack));
+            line.push(mv);
+            attacker = !attacker;
+        }
+
+        for _ in &line {
+            zobrist_stack.pop();
+        }
+
+        line
+    }
+}
+
+// `Galumph mate <moves> <fen>`, for solving problems without a UCI gui
+pub fn run_solver_cli(args: &[String]) {
+    let Some(max_moves) = args.first().and_then(|s| s.parse::<u32>().ok()) else {
+        println!("usage: mate <moves> <fen>");
+        return;
+    };
+
+    let fen = if args.len() > 1 {
+        args[1..].join(" ")
+    } else {
+        START_FEN.to_owned()
+    };
+    let board = Board::from_fen(&fen);
+    let zobrist_stack = ZobristStack::new(&board);
+
+    let stopwatch = std::time::Instant::now();
+    let mut solver = DfpnSolver::new(
+        Hash::DEFAULT as usize,
+        DfpnSolver::DEFAULT_NODE_LIMIT,
+        false,
+    );
+    let result = solver.solve(&board, &zobrist_stack, max_moves);
+    let time = stopwatch.elapsed().as_millis();
+
+    match result {
+        MateResult::Mate(line) => {
+            let line_str: Vec<String> = line.iter().map(|mv| mv.as_string()).collect();
+            println!("mate in {}: {}", line.len().div_ceil(2), line_str.join(" "));
+        }
+        MateResult::NoMate => println!("no mate in {max_moves}"),
+        MateResult::Unknown => println!("unknown: ran out of nodes"),
+    }
+    println!("{} nodes {time} ms", solver.nodes());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        move_generation::{
+            board_rep::{Board, START_FEN},
+            chess_move::Move,
+            movegen::MovePicker,
+        },
+        search::zobrist_stack::ZobristStack,
+        uci::setoption::Hash,
+    };
+
+    use super::{DfpnSolver, MateResult};
+
+    fn solve(fen: &str, max_moves: u32, megabytes: usize) -> MateResult {
+        let board = Board::from_fen(fen);
+        let zobrist_stack = ZobristStack::new(&board);
+        DfpnSolver::new(megabytes, DfpnSolver::DEFAULT_NODE_LIMIT, false).solve(
+            &board,
+            &zobrist_stack,
+            max_moves,
+        )
+    }
+
+    // the solver has to find the shortest mate, and its line has to actually end in mate
+    fn assert_mate_in(fen: &str, moves: usize) {
+        assert_mate_in_with_hash(fen, moves, Hash::DEFAULT as usize);
+    }
+
+    fn assert_mate_in_with_hash(fen: &str, moves: usize, megabytes: usize) {
+        let MateResult::Mate(line) = solve(fen, moves as u32, megabytes) else {
+            panic!("no mate found in {fen}");
+        };
+        assert_eq!(line.len(), 2 * moves - 1, "wrong mate length in {fen}");
+
+        let mut board = Board::from_fen(fen);
+        for mv in line {
+            assert!(board.simple_try_play(mv), "illegal move in {fen}");
+        }
+        assert!(board.in_check() && MovePicker::first_legal_mv(&board).is_none());
+    }
+
+    #[test]
+    fn finds_short_mates() {
+        assert_mate_in("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+        assert_mate_in(
+            "2bqkbn1/2pppp2/np2N3/r3P1p1/p2N2B1/5Q2/PPPPPP1P/RNB1K2R w KQ - 0 1",
+            2,
+        );
+        assert_mate_in("4k3/8/Q7/4p2K/8/3p4/1R1p4/8 w - - 0 1", 3);
+        assert_mate_in("6k1/1p6/3K4/8/8/8/2Q5/8 w - - 0 1", 4);
+        assert_mate_in("6rR/7K/8/4Q3/8/8/2k5/8 w - - 0 1", 5);
+        assert_mate_in("8/8/8/8/6p1/6k1/8/2QK4 w - - 0 1", 6);
+    }
+
+    #[test]
+    fn solves_with_a_full_table() {
+        // far more nodes than fit in 1 MB, so entries get overwritten along the way
+        assert_mate_in_with_hash("6rR/7K/8/4Q3/8/8/2k5/8 w - - 0 1", 5, 1);
+    }
+
+    #[test]
+    #[ignore = "takes too long"]
+    fn finds_long_mates() {
+        assert_mate_in("6k1/8/B7/8/8/R6K/8/8 w - - 0 1", 7);
+        assert_mate_in("7R/K7/8/8/7B/8/8/1k6 w - - 0 1", 8);
+        assert_mate_in("8/4K3/8/8/8/8/2R5/6k1 w - - 0 1", 9);
+        assert_mate_in("8/8/8/6R1/5K2/1k6/8/8 w - - 0 1", 10);
+    }
+
+    #[test]
+    fn proves_there_is_no_mate() {
+        assert_eq!(
+            solve(START_FEN, 2, Hash::DEFAULT as usize),
+            MateResult::NoMate
+        );
+        // stalemating the king is not a mate
+        assert_eq!(
+            solve("k7/8/1Q6/8/8/8/8/7K w - - 0 1", 1, Hash::DEFAULT as usize),
+            MateResult::NoMate
+        );
+    }
+
+    #[test]
+    fn does_not_store_draws_by_repetition() {
+        // 1. Kc6 Ka8 2. Qb7# is the only mate, but this game already had the position after 1... Ka8
+        let mut board = Board::from_fen("k7/7Q/2K5/7p/8/8/8/8 w - - 0 1");
+        let mut zobrist_stack = ZobristStack::new(&board);
+        for mv_str in ["c6d7", "a8b8"] {
+            let mv = Move::from_str(mv_str, &board).unwrap();
+            assert!(board.try_play_move(mv, &mut zobrist_stack));
+        }
+
+        let mut solver = DfpnSolver::new(
+            Hash::DEFAULT as usize,
+            DfpnSolver::DEFAULT_NODE_LIMIT,
+            false,
+        );
+        assert_eq!(solver.solve(&board, &zobrist_stack, 2), MateResult::NoMate);
+
+        // the same positions without the repetition, so nothing from the last search may carry over
+        let board = Board::from_fen("1k6/3K3Q/8/7p/8/8/8/8 w - - 0 1");
+        let result = solver.solve(&board, &ZobristStack::new(&board), 2);
+        assert!(matches!(result, MateResult::Mate(line) if line.len() == 3));
+    }
+}
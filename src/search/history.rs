@@ -5,18 +5,34 @@ use crate::move_generation::{
 
 use super::constants::{Depth, EvalScore};
 
+const BONUS_MAX: i32 = 1200;
+const SCORE_MAX: i32 = i16::MAX as i32;
+
+fn history_bonus(depth: Depth) -> i32 {
+    let d = i32::from(depth);
+    (16 * d * d).min(BONUS_MAX)
+}
+
+// history gravity: the closer a score is to SCORE_MAX, the smaller the bonuses it receives
+fn apply_bonus(score: &mut EvalScore, bonus: i32) {
+    *score += bonus - *score * bonus.abs() / SCORE_MAX;
+}
+
+// Butterfly-style history for quiet moves, indexed by [color][from threatened][to threatened][piece][to].
+// A move escaping an attack or walking into one is usually a very different move than the same move
+// on a quiet board, so they get separate scores.
+type ThreatTables = [[[[EvalScore; Square::CNT as usize]; Piece::CNT as usize]; 2]; 2];
+
 #[derive(Debug, Clone)]
 pub struct History {
-    scores: [[[EvalScore; Square::CNT as usize]; Piece::CNT as usize]; Color::CNT as usize],
+    scores: [ThreatTables; Color::CNT as usize],
 }
 
 impl History {
-    const BONUS_MAX: i32 = 1200;
-    const SCORE_MAX: i32 = i16::MAX as i32;
-
     pub const fn new() -> Self {
         Self {
-            scores: [[[0; Square::CNT as usize]; Piece::CNT as usize]; Color::CNT as usize],
+            scores: [[[[[0; Square::CNT as usize]; Piece::CNT as usize]; 2]; 2];
+                Color::CNT as usize],
         }
     }
 
@@ -24,23 +40,27 @@ impl History {
         let piece = board.piece_on_sq(mv.from()).as_index();
         let to = mv.to().as_index();
         let color = board.stm.as_index();
+        let from_threatened = usize::from(board.threats.overlaps(mv.from().as_bitboard()));
+        let to_threatened = usize::from(board.threats.overlaps(mv.to().as_bitboard()));
 
-        self.scores[color][piece][to]
+        self.scores[color][from_threatened][to_threatened][piece][to]
     }
 
     fn update_history_score(&mut self, board: &Board, mv: Move, bonus: i32) {
-        let scaled_bonus = bonus - self.score(board, mv) * bonus.abs() / Self::SCORE_MAX;
-
         let piece = board.piece_on_sq(mv.from()).as_index();
         let to = mv.to().as_index();
         let color = board.stm.as_index();
+        let from_threatened = usize::from(board.threats.overlaps(mv.from().as_bitboard()));
+        let to_threatened = usize::from(board.threats.overlaps(mv.to().as_bitboard()));
 
-        self.scores[color][piece][to] += scaled_bonus;
+        apply_bonus(
+            &mut self.scores[color][from_threatened][to_threatened][piece][to],
+            bonus,
+        );
     }
 
     pub fn update(&mut self, board: &Board, quiets: &[Move], depth: Depth) {
-        let d = i32::from(depth);
-        let bonus = (16 * d * d).min(Self::BONUS_MAX);
+        let bonus = history_bonus(depth);
 
         let cutoff_move = quiets[quiets.len() - 1];
         self.update_history_score(board, cutoff_move, bonus); // only the cutoff move gets a positive bonus
@@ -55,6 +75,161 @@ impl History {
             .iter_mut()
             .flatten()
             .flatten()
+            .flatten()
+            .flatten()
             .for_each(|x| *x /= 2);
     }
 }
+
+// The piece that moved and the square it moved to, which is all continuation histories know about a move.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PieceTo {
+    pub piece: Piece,
+    pub to: Square,
+}
+
+impl PieceTo {
+    pub fn new(board: &Board, mv: Move) -> Self {
+        Self {
+            piece: board.piece_on_sq(mv.from()),
+            to: mv.to(),
+        }
+    }
+}
+
+pub type PieceToHistory = [[EvalScore; Square::CNT as usize]; Piece::CNT as usize];
+
+// Scores quiet moves by how well they have worked as a follow-up to a move played earlier in the line,
+// indexed by [color][previous piece][previous to] x [piece][to].
+#[derive(Debug, Clone)]
+pub struct ContinuationHistory {
+    tables: Vec<PieceToHistory>,
+}
+
+impl ContinuationHistory {
+    const TABLE_CNT: usize = (Color::CNT * Piece::CNT) as usize * Square::CNT as usize;
+
+    pub fn new() -> Self {
+        Self {
+            tables: vec![[[0; Square::CNT as usize]; Piece::CNT as usize]; Self::TABLE_CNT],
+        }
+    }
+
+    fn index(color: Color, prev: PieceTo) -> usize {
+        (color.as_index() * Piece::CNT as usize + prev.piece.as_index()) * Square::CNT as usize
+            + prev.to.as_index()
+    }
+
+    pub fn table(&self, color: Color, prev: PieceTo) -> &PieceToHistory {
+        &self.tables[Self::index(color, prev)]
+    }
+
+    pub fn update(&mut self, board: &Board, prev: PieceTo, quiets: &[Move], depth: Depth) {
+        let bonus = history_bonus(depth);
+        let table = &mut self.tables[Self::index(board.stm, prev)];
+
+        let cutoff_move = quiets[quiets.len() - 1];
+        for &mv in quiets {
+            let piece = board.piece_on_sq(mv.from()).as_index();
+            let to = mv.to().as_index();
+            let bonus = if mv == cutoff_move { bonus } else { -bonus };
+
+            apply_bonus(&mut table[piece][to], bonus);
+        }
+    }
+
+    pub fn age_scores(&mut self) {
+        self.tables
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|x| *x /= 2);
+    }
+}
+
+// Scores noisy moves by how often they caused cutoffs, indexed by [piece][to][captured piece].
+// Non-capture promotions and en passant use Piece::NONE as the captured piece.
+#[derive(Debug, Clone)]
+pub struct CaptureHistory {
+    scores: [[[EvalScore; (Piece::CNT + 1) as usize]; Square::CNT as usize]; Piece::CNT as usize],
+}
+
+impl CaptureHistory {
+    pub const fn new() -> Self {
+        Self {
+            scores: [[[0; (Piece::CNT + 1) as usize]; Square::CNT as usize]; Piece::CNT as usize],
+        }
+    }
+
+    pub fn score(&self, board: &Board, mv: Move) -> EvalScore {
+        let piece = board.piece_on_sq(mv.from()).as_index();
+        let to = mv.to().as_index();
+        let captured = board.piece_on_sq(mv.to()).as_index();
+
+        self.scores[piece][to][captured]
+    }
+
+    // the cutoff move gets a bonus if it was noisy, every other noisy move we tried gets a malus
+    pub fn update(&mut self, board: &Board, noisies: &[Move], cutoff_move: Move, depth: Depth) {
+        let bonus = history_bonus(depth);
+
+        for &mv in noisies {
+            let piece = board.piece_on_sq(mv.from()).as_index();
+            let to = mv.to().as_index();
+            let captured = board.piece_on_sq(mv.to()).as_index();
+            let bonus = if mv == cutoff_move { bonus } else { -bonus };
+
+            apply_bonus(&mut self.scores[piece][to][captured], bonus);
+        }
+    }
+
+    pub fn age_scores(&mut self) {
+        self.scores
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|x| *x /= 2);
+    }
+}
+
+// All the history tables that apply at a single node: the butterfly and capture histories,
+// plus the continuation tables for the moves played 1 and 2 plies ago.
+#[derive(Debug, Copy, Clone)]
+pub struct NodeHistory<'a> {
+    butterfly: &'a History,
+    captures: &'a CaptureHistory,
+    continuations: [Option<&'a PieceToHistory>; 2],
+}
+
+impl<'a> NodeHistory<'a> {
+    pub const fn new(
+        butterfly: &'a History,
+        captures: &'a CaptureHistory,
+        continuations: [Option<&'a PieceToHistory>; 2],
+    ) -> Self {
+        Self {
+            butterfly,
+            captures,
+            continuations,
+        }
+    }
+
+    pub fn continuation_score(&self, board: &Board, mv: Move) -> EvalScore {
+        let piece = board.piece_on_sq(mv.from()).as_index();
+        let to = mv.to().as_index();
+
+        self.continuations
+            .iter()
+            .flatten()
+            .map(|table| table[piece][to])
+            .sum()
+    }
+
+    pub fn quiet_score(&self, board: &Board, mv: Move) -> EvalScore {
+        self.butterfly.score(board, mv) + self.continuation_score(board, mv)
+    }
+
+    pub fn capture_score(&self, board: &Board, mv: Move) -> EvalScore {
+        self.captures.score(board, mv)
+    }
+}
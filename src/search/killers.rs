@@ -5,21 +5,26 @@ use crate::{
 
 #[derive(Debug, Clone)]
 pub struct Killers {
-    moves: [Move; MAX_PLY as usize],
+    moves: [[Move; 2]; MAX_PLY as usize],
 }
 
 impl Killers {
     pub const fn new() -> Self {
         Self {
-            moves: [Move::NULL; MAX_PLY as usize],
+            moves: [[Move::NULL; 2]; MAX_PLY as usize],
         }
     }
 
+    // the newest killer goes in the first slot, and the old first killer gets shifted to the second
     pub fn update(&mut self, mv: Move, ply: Ply) {
-        self.moves[ply as usize] = mv;
+        let killers = &mut self.moves[ply as usize];
+        if killers[0] != mv {
+            killers[1] = killers[0];
+            killers[0] = mv;
+        }
     }
 
-    pub const fn killer(&self, ply: Ply) -> Move {
+    pub const fn killers(&self, ply: Ply) -> [Move; 2] {
         self.moves[ply as usize]
     }
 }
@@ -1,12 +1,17 @@
 pub(crate) mod bench;
 pub(crate) mod constants;
+mod correction_history;
+mod counter_moves;
+pub(crate) mod dfpn;
 pub(crate) mod history;
 mod killers;
 mod late_move_reduction;
 mod pv_table;
+mod root_moves;
 pub(crate) mod search_manager;
-mod search_timer;
-mod static_exchange;
+mod search_stack;
+pub(crate) mod search_timer;
+pub(crate) mod static_exchange;
 mod transposition_table;
 pub(crate) mod zobrist;
 pub(crate) mod zobrist_stack;
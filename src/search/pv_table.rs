@@ -34,19 +34,9 @@ impl PvTable {
         }
     }
 
-    pub fn best_move(&self) -> Move {
-        self.triangular_move_matrix[0][0]
-    }
-
-    pub fn pv_string(&self) -> String {
-        let mut result = String::new();
-        let variation_length = self.pv_length[0];
-        let pv = &self.triangular_move_matrix[0][..variation_length];
-
-        for mv in pv {
-            result.push_str(mv.as_string().as_str());
-            result.push(' ');
-        }
-        result
+    // the principal variation found by the last search from this ply
+    pub fn line(&self, ply: Ply) -> &[Move] {
+        let ply = ply as usize;
+        &self.triangular_move_matrix[ply][ply..self.pv_length[ply]]
     }
 }
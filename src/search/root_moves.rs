@@ -0,0 +1,146 @@
+use std::{cmp::Reverse, ops::Range};
+
+use crate::{
+    move_generation::{board_rep::Board, chess_move::Move, movegen::MovePicker},
+    search::constants::{EvalScore, Nodes, Ply, INF},
+};
+
+#[derive(Debug, Clone)]
+pub struct RootMove {
+    pub mv: Move,
+    // -INF until the move is searched this iteration, and whenever it fails to raise alpha
+    pub score: EvalScore,
+    pub prev_score: EvalScore,
+    // nodes spent on this move over the whole search
+    pub nodes: Nodes,
+    pub seldepth: Ply,
+    pub pv: Vec<Move>,
+}
+
+impl RootMove {
+    fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            score: -INF,
+            prev_score: -INF,
+            nodes: 0,
+            seldepth: 0,
+            pv: vec![mv],
+        }
+    }
+
+    pub fn pv_string(&self) -> String {
+        let mut result = String::new();
+        for mv in &self.pv {
+            result.push_str(mv.as_string().as_str());
+            result.push(' ');
+        }
+        result
+    }
+}
+
+// The legal moves at the root, searched in the order of their scores from the last iteration.
+#[derive(Debug, Clone)]
+pub struct RootMoves {
+    moves: Vec<RootMove>,
+}
+
+impl RootMoves {
+    pub const fn new() -> Self {
+        Self { moves: vec![] }
+    }
+
+    // generates the legal moves in this position, restricted to `search_moves` unless it is empty
+    pub fn generate(board: &Board, search_moves: &[String]) -> Self {
+        let mut moves = vec![];
+        let mut picker = MovePicker::new();
+        while let Some(mv) = picker.simple_pick::<true>(board) {
+            let is_searched =
+                search_moves.is_empty() || search_moves.iter().any(|s| *s == mv.as_string());
+
+            if is_searched && board.clone().simple_try_play(mv) {
+                moves.push(RootMove::new(mv));
+            }
+        }
+
+        Self { moves }
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&RootMove> {
+        self.moves.get(idx)
+    }
+
+    pub fn best(&self) -> Option<&RootMove> {
+        self.moves.first()
+    }
+
+    pub fn find(&self, mv: Move) -> Option<&RootMove> {
+        self.moves.iter().find(|rm| rm.mv == mv)
+    }
+
+    pub fn find_mut(&mut self, mv: Move) -> Option<&mut RootMove> {
+        self.moves.iter_mut().find(|rm| rm.mv == mv)
+    }
+
+    pub fn start_iteration(&mut self) {
+        for rm in &mut self.moves {
+            rm.prev_score = rm.score;
+            rm.score = -INF;
+        }
+    }
+
+    // a stable sort, so moves that failed low keep their order from the last iteration
+    pub fn sort(&mut self, range: Range<usize>) {
+        self.moves[range].sort_by_key(|rm| Reverse((rm.score, rm.prev_score)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        move_generation::board_rep::{Board, START_FEN},
+        search::constants::INF,
+    };
+
+    use super::RootMoves;
+
+    #[test]
+    fn search_moves_restrict_the_root() {
+        let board = Board::from_fen(START_FEN);
+        assert_eq!(RootMoves::generate(&board, &[]).len(), 20);
+
+        let search_moves = ["e2e4", "g1f3", "e2e5"].map(String::from);
+        let root_moves = RootMoves::generate(&board, &search_moves);
+        assert_eq!(root_moves.len(), 2);
+        for i in 0..root_moves.len() {
+            let mv = root_moves.get(i).unwrap().mv.as_string();
+            assert!(search_moves[..2].contains(&mv));
+        }
+    }
+
+    #[test]
+    fn sorts_by_score_then_previous_score() {
+        let board = Board::from_fen(START_FEN);
+        let search_moves = ["a2a3", "b2b3", "c2c3"].map(String::from);
+        let mut root_moves = RootMoves::generate(&board, &search_moves);
+
+        let a3 = root_moves.get(0).unwrap().mv;
+        let b3 = root_moves.get(1).unwrap().mv;
+        let c3 = root_moves.get(2).unwrap().mv;
+
+        root_moves.find_mut(b3).unwrap().score = 30;
+        root_moves.find_mut(c3).unwrap().score = 10;
+        root_moves.start_iteration();
+        root_moves.find_mut(a3).unwrap().score = 20;
+        root_moves.sort(0..3);
+
+        // a3 is the only move with a score this iteration, the rest fall back on their old scores
+        let order: Vec<_> = (0..3).map(|i| root_moves.get(i).unwrap().mv).collect();
+        assert_eq!(order, vec![a3, b3, c3]);
+        assert_eq!(root_moves.get(1).unwrap().score, -INF);
+    }
+}
@@ -1,6 +1,10 @@
 use std::{
-    sync::atomic::{AtomicBool, Ordering},
-    time::Instant,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
     vec,
 };
 
@@ -8,8 +12,8 @@ use arrayvec::ArrayVec;
 
 use crate::{
     move_generation::{
-        board_rep::{Board, Color, START_FEN},
-        chess_move::Move,
+        board_rep::{Board, Color, Piece, START_FEN},
+        chess_move::{Flag, Move},
         movegen::MovePicker,
     },
     search::{
@@ -18,6 +22,7 @@ use crate::{
             MAX_PLY,
         },
         late_move_reduction::get_lmr_reduction,
+        static_exchange::SEE_VALS,
     },
     uci::setoption::Hash,
 };
@@ -29,11 +34,17 @@ fn temp_eval(board: &Board) -> EvalScore {
 }
 
 use super::{
-    history::History,
+    correction_history::CorrectionHistory,
+    counter_moves::CounterMoves,
+    dfpn::{DfpnSolver, MateResult},
+    history::{CaptureHistory, ContinuationHistory, History, NodeHistory, PieceTo},
     killers::Killers,
     pv_table::PvTable,
-    search_timer::SearchTimer,
+    root_moves::RootMoves,
+    search_stack::SearchStack,
+    search_timer::{Clock, RealClock, SearchTimer, TimeAllocation},
     transposition_table::{TTFlag, TranspositionTable},
+    zobrist::ZobristHash,
     zobrist_stack::ZobristStack,
 };
 
@@ -51,12 +62,29 @@ pub fn clear_stop_flag() {
     STOP_FLAG.store(false, Ordering::Relaxed);
 }
 
+// While pondering we search on the opponent's time, so we ignore our time limits until a ponderhit.
+static PONDER_FLAG: AtomicBool = AtomicBool::new(false);
+
+pub fn ponder_flag_is_set() -> bool {
+    PONDER_FLAG.load(Ordering::Relaxed)
+}
+
+pub fn set_ponder_flag() {
+    PONDER_FLAG.store(true, Ordering::Relaxed);
+}
+
+pub fn clear_ponder_flag() {
+    PONDER_FLAG.store(false, Ordering::Relaxed);
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SearchLimit {
     Standard,
     MoveTime(Milliseconds),
     Depth(Depth),
     Nodes(Nodes),
+    // search until we find a mate in at most this many moves
+    Mate(u32),
     Infinite,
 }
 
@@ -66,6 +94,9 @@ pub struct SearchConfig {
     pub inc: [Milliseconds; Color::CNT as usize],
     pub overhead: Milliseconds,
     pub moves_to_go: Option<u32>,
+    // only these moves are searched at the root, unless this is empty
+    pub search_moves: Vec<String>,
+    pub multi_pv: usize,
 }
 
 impl SearchConfig {
@@ -76,6 +107,8 @@ impl SearchConfig {
             inc: [0, 0],
             overhead,
             moves_to_go: None,
+            search_moves: vec![],
+            multi_pv: 1,
         }
     }
 }
@@ -84,6 +117,8 @@ pub struct SearchManager {
     searcher: Searcher,
     board: Board,
     tt: TranspositionTable,
+    // the mate solver takes over the memory of the TT while it runs, so we need this to rebuild it
+    hash_megabytes: usize,
 }
 
 impl SearchManager {
@@ -92,17 +127,23 @@ impl SearchManager {
             searcher: Searcher::new(),
             board: Board::from_fen(START_FEN),
             tt: TranspositionTable::new(Hash::DEFAULT as usize),
+            hash_megabytes: Hash::DEFAULT as usize,
         }
     }
 
     pub fn newgame(&mut self) {
         self.tt.reset_entries();
         self.searcher.history = History::new();
+        self.searcher.capture_history = CaptureHistory::new();
+        self.searcher.correction_history = CorrectionHistory::new();
+        self.searcher.cont_hists = [ContinuationHistory::new(), ContinuationHistory::new()];
         self.searcher.killers = Killers::new();
+        self.searcher.counter_moves = CounterMoves::new();
     }
 
     pub fn resize_tt(&mut self, megabytes: u32) {
         self.tt = TranspositionTable::new(megabytes as usize);
+        self.hash_megabytes = megabytes as usize;
     }
 
     pub fn update_state(&mut self, board: &Board, zobrist_stack: &ZobristStack) {
@@ -110,11 +151,56 @@ impl SearchManager {
         self.searcher.zobrist_stack = zobrist_stack.clone();
     }
 
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.searcher.clock = clock;
+    }
+
     pub fn start_search(&mut self, config: &SearchConfig) {
         self.searcher.go(&self.board, &self.tt, config, true);
         self.tt.age_table();
     }
 
+    pub fn start_mate_solver(&mut self, max_moves: u32) {
+        // we free the TT before the solver allocates its table, so both together never use more than Hash
+        self.tt = TranspositionTable::new(0);
+
+        let stopwatch = Instant::now();
+        let mut solver = DfpnSolver::new(self.hash_megabytes, DfpnSolver::DEFAULT_NODE_LIMIT, true);
+        let result = solver.solve(&self.board, &self.searcher.zobrist_stack, max_moves);
+        let time = stopwatch.elapsed().as_millis();
+        set_stop_flag();
+
+        let best_move = match result {
+            MateResult::Mate(line) => {
+                let pv: Vec<String> = line.iter().map(|mv| mv.as_string()).collect();
+                println!(
+                    "info depth {} score mate {} time {time} nodes {} pv {}",
+                    line.len(),
+                    line.len().div_ceil(2),
+                    solver.nodes(),
+                    pv.join(" ")
+                );
+                line.first().copied()
+            }
+            MateResult::NoMate => {
+                println!("info string no mate in {max_moves}");
+                MovePicker::first_legal_mv(&self.board)
+            }
+            MateResult::Unknown => {
+                println!("info string mate search stopped without a result");
+                MovePicker::first_legal_mv(&self.board)
+            }
+        };
+
+        println!(
+            "bestmove {}",
+            best_move.map_or("0000".to_owned(), |mv| mv.as_string())
+        );
+
+        drop(solver);
+        self.tt = TranspositionTable::new(self.hash_megabytes);
+    }
+
     pub fn start_bench_search(&mut self, depth: Depth) -> Nodes {
         let mut config = SearchConfig::new(0);
         config.limits.push(SearchLimit::Depth(depth));
@@ -130,28 +216,54 @@ impl SearchManager {
 #[derive(Debug, Clone)]
 struct Searcher {
     timer: Option<SearchTimer>,
+    clock: Arc<dyn Clock>,
     zobrist_stack: ZobristStack,
     history: History,
+    capture_history: CaptureHistory,
+    // follow-up histories for the moves played 1 and 2 plies ago
+    cont_hists: [ContinuationHistory; 2],
     killers: Killers,
+    counter_moves: CounterMoves,
+    correction_history: CorrectionHistory,
+    search_stack: SearchStack,
+    // null move pruning is disabled for a color until this ply while we verify one of its null move cutoffs
+    nmp_min_ply: [Ply; Color::CNT as usize],
+
+    root_moves: RootMoves,
+    // the MultiPV line we are searching, every root move before it is already the best move of an earlier line
+    pv_idx: usize,
+    // the lowest score that proves a mate within our SearchLimit::Mate, None if we aren't looking for one.
+    // Mate searches must be exact, so they skip every reduction and pruning that can hide a mate.
+    mate_target: Option<EvalScore>,
 
     // info
     pv_table: PvTable,
     best_move: Move,
+    completed_depth: Depth,
     seldepth: u8,
     node_cnt: u64,
 }
 
 impl Searcher {
-    const TIMER_CHECK_FREQ: u64 = 1024;
-
     fn new() -> Self {
         Self {
             timer: None,
+            clock: Arc::new(RealClock::new()),
             zobrist_stack: ZobristStack::new(&Board::from_fen(START_FEN)),
             history: History::new(),
+            capture_history: CaptureHistory::new(),
+            cont_hists: [ContinuationHistory::new(), ContinuationHistory::new()],
             killers: Killers::new(),
+            counter_moves: CounterMoves::new(),
+            correction_history: CorrectionHistory::new(),
+            search_stack: SearchStack::new(),
+            nmp_min_ply: [0; Color::CNT as usize],
+            root_moves: RootMoves::new(),
+            pv_idx: 0,
+            mate_target: None,
             pv_table: PvTable::new(),
             best_move: Move::NULL,
+            completed_depth: 0,
             seldepth: 0,
             node_cnt: 0,
         }
@@ -159,17 +271,56 @@ impl Searcher {
 
     fn reset_info(&mut self) {
         self.best_move = Move::NULL;
+        self.completed_depth = 0;
         self.seldepth = 0;
         self.node_cnt = 0;
+        self.search_stack = SearchStack::new();
+        self.nmp_min_ply = [0; Color::CNT as usize];
+        self.pv_idx = 0;
+        self.mate_target = None;
+    }
+
+    // moves played 1 and 2 plies before this one, if there were any
+    fn prev_moves(&self, ply: Ply) -> [Option<PieceTo>; 2] {
+        [1, 2].map(|back| {
+            ply.checked_sub(back)
+                .and_then(|prev_ply| self.search_stack.at(prev_ply).played)
+        })
     }
 
+    fn node_history(&self, board: &Board, ply: Ply) -> NodeHistory<'_> {
+        let prev_moves = self.prev_moves(ply);
+        let continuations =
+            [0, 1].map(|i| prev_moves[i].map(|prev| self.cont_hists[i].table(board.stm, prev)));
+
+        NodeHistory::new(&self.history, &self.capture_history, continuations)
+    }
+
+    fn update_quiet_histories(&mut self, board: &Board, ply: Ply, quiets: &[Move], depth: Depth) {
+        self.history.update(board, quiets, depth);
+
+        let prev_moves = self.prev_moves(ply);
+        for (cont_hist, prev) in self.cont_hists.iter_mut().zip(prev_moves) {
+            if let Some(prev) = prev {
+                cont_hist.update(board, prev, quiets, depth);
+            }
+        }
+    }
+
+    // reports the root move at `line_idx`, which is the best move of MultiPV line `line_idx + 1`
     fn report_search_info(
         &self,
         tt: &TranspositionTable,
+        line_idx: usize,
         score: EvalScore,
+        bound: TTFlag,
         depth: Depth,
         stopwatch: Instant,
     ) {
+        let Some(root_move) = self.root_moves.get(line_idx) else {
+            return;
+        };
+
         let score_str = if score >= MATE_THRESHOLD {
             let ply = EVAL_MAX - score;
             let score_value = (ply + 1) / 2;
@@ -184,16 +335,23 @@ impl Searcher {
             format!("cp {score}")
         };
 
+        let bound_str = match bound {
+            TTFlag::LOWER_BOUND => " lowerbound",
+            TTFlag::UPPER_BOUND => " upperbound",
+            _ => "",
+        };
+
         let elapsed = stopwatch.elapsed();
         let time = elapsed.as_millis();
         let nps = (u128::from(self.node_cnt) * 1_000_000) / elapsed.as_micros().max(1);
 
         println!(
-            "info score {score_str} time {time} nodes {} nps {nps} depth {depth} seldepth {} hashfull {} pv {}",
+            "info multipv {} score {score_str}{bound_str} time {time} nodes {} nps {nps} depth {depth} seldepth {} hashfull {} pv {}",
+            line_idx + 1,
             self.node_cnt,
-            self.seldepth,
+            root_move.seldepth,
             tt.hashfull(), // TODO: store hashfull somewhere, and only update it outside of searches (should give speedup)
-            self.pv_table.pv_string()
+            root_move.pv_string()
         );
     }
 
@@ -204,13 +362,13 @@ impl Searcher {
         for &limit in &config.limits {
             match limit {
                 SearchLimit::Standard => {
-                    let t = (time / 25 + inc / 2).saturating_sub(config.overhead);
-                    self.timer = Some(SearchTimer::new(t));
+                    let alloc = TimeAllocation::new(time, inc, config.moves_to_go, config.overhead);
+                    self.timer = Some(SearchTimer::new(&self.clock, alloc.hard, alloc.soft));
                     break;
                 }
                 SearchLimit::MoveTime(time) => {
                     let t = time.saturating_sub(config.overhead);
-                    self.timer = Some(SearchTimer::new(t));
+                    self.timer = Some(SearchTimer::new(&self.clock, t, t));
                     break;
                 }
                 _ => (),
@@ -218,14 +376,14 @@ impl Searcher {
         }
     }
 
-    fn continue_deepening(&self, config: &SearchConfig, next_depth: Depth) -> bool {
+    fn continue_deepening(&self, board: &Board, config: &SearchConfig, next_depth: Depth) -> bool {
         if next_depth == MAX_DEPTH {
             return false;
         }
 
-        if let Some(timer) = self.timer {
-            // TODO: replace with soft tm
-            if timer.is_hard_expired() {
+        if let Some(timer) = self.timer.as_ref().filter(|_| !ponder_flag_is_set()) {
+            let best_move_nodes = self.root_moves.best().map_or(0, |rm| rm.nodes);
+            if timer.is_soft_expired(best_move_nodes, self.node_cnt) {
                 return false;
             }
         }
@@ -235,6 +393,11 @@ impl Searcher {
             result &= match limit {
                 SearchLimit::Depth(depth_limit) => next_depth <= depth_limit,
                 SearchLimit::Nodes(node_limit) => self.node_cnt <= node_limit,
+                // a mate in n moves is 2n - 1 plies away, so searching deeper can't find one
+                SearchLimit::Mate(moves) => {
+                    let moves = moves.min(u32::from(MAX_PLY));
+                    u32::from(next_depth) < 2 * moves && !self.mate_is_proven(board)
+                }
                 _ => true,
             }
         }
@@ -242,36 +405,114 @@ impl Searcher {
         result
     }
 
+    // Plays out the PV of our best move, and checks that it really mates within our mate limit.
+    // A truncated or stale PV means we haven't proven anything yet, so we keep searching.
+    fn mate_is_proven(&self, board: &Board) -> bool {
+        let (Some(target), Some(best)) = (self.mate_target, self.root_moves.best()) else {
+            return false;
+        };
+
+        if best.score < target || best.pv.len() != (EVAL_MAX - best.score) as usize {
+            return false;
+        }
+
+        let mut board = board.clone();
+        for &mv in &best.pv {
+            if !mv.is_pseudolegal(&board) || !board.simple_try_play(mv) {
+                return false;
+            }
+        }
+
+        board.in_check() && MovePicker::first_legal_mv(&board).is_none()
+    }
+
     fn go(
         &mut self,
         board: &Board,
         tt: &TranspositionTable,
         config: &SearchConfig,
         report_info: bool,
-    ) {
+    ) -> Move {
         self.reset_info();
 
         self.timer = None;
         self.init_search_timer(board.stm, config);
+        self.root_moves = RootMoves::generate(board, &config.search_moves);
+        self.mate_target = config.limits.iter().find_map(|&limit| match limit {
+            SearchLimit::Mate(moves) => {
+                let plies = (2 * moves.min(u32::from(MAX_PLY))).saturating_sub(1);
+                Some(EVAL_MAX - plies as EvalScore)
+            }
+            _ => None,
+        });
 
         let stopwatch = Instant::now();
 
+        let line_cnt = config.multi_pv.min(self.root_moves.len()).max(1);
+
         let mut best_move = Move::NULL;
+        // an aborted iteration leaves partial PVs behind, so we keep the last complete one around
+        let mut best_pv = vec![];
         let mut depth = 1;
-        while self.continue_deepening(config, depth) {
-            let score = self.negamax::<true, true>(board, tt, depth, 0, -INF, INF);
-
-            if stop_flag_is_set() {
-                break;
+        'deepening: while self.continue_deepening(board, config, depth) {
+            self.root_moves.start_iteration();
+
+            // MULTIPV
+            // every line searches the root without the best moves of the lines before it
+            for pv_idx in 0..line_cnt {
+                self.pv_idx = pv_idx;
+                let prev_score = self.root_moves.get(pv_idx).map_or(0, |rm| rm.prev_score);
+                self.aspiration_search(board, tt, depth, prev_score, stopwatch, report_info);
+
+                if stop_flag_is_set() {
+                    break 'deepening;
+                }
             }
+            self.root_moves.sort(0..line_cnt);
 
             if report_info {
-                self.report_search_info(tt, score, depth, stopwatch);
+                for line_idx in 0..line_cnt {
+                    let score = self.root_moves.get(line_idx).map_or(0, |rm| rm.score);
+                    // mate searches fail low on every line without a mate
+                    let bound = if self.mate_target.is_some_and(|target| score < target) {
+                        TTFlag::UPPER_BOUND
+                    } else {
+                        TTFlag::EXACT
+                    };
+                    self.report_search_info(tt, line_idx, score, bound, depth, stopwatch);
+                }
             }
 
-            best_move = self.pv_table.best_move();
+            if let Some(best) = self.root_moves.best() {
+                best_move = best.mv;
+                best_pv.clone_from(&best.pv);
+            }
+            self.completed_depth = depth;
             depth += 1;
         }
+
+        if let Some(target) = self.mate_target {
+            if !self.mate_is_proven(board) {
+                if report_info {
+                    println!(
+                        "info string no mate in {} found",
+                        (EVAL_MAX - target + 1) / 2
+                    );
+                }
+
+                // every line failed low, so the move that sorted first is no better than any other.
+                // like the mate solver, we just play the first legal move
+                if let Some(first) = RootMoves::generate(board, &config.search_moves).best() {
+                    best_move = first.mv;
+                    best_pv.clear();
+                }
+            }
+        }
+
+        // we aren't allowed to send a bestmove while pondering, even if our search is done
+        while ponder_flag_is_set() && !stop_flag_is_set() {
+            thread::sleep(Duration::from_millis(1));
+        }
         set_stop_flag();
 
         if best_move.is_null() {
@@ -280,22 +521,147 @@ impl Searcher {
         }
 
         if report_info {
-            println!("bestmove {}", best_move.as_string());
+            match Self::ponder_move(board, tt, best_move, &best_pv) {
+                Some(ponder_move) => println!(
+                    "bestmove {} ponder {}",
+                    best_move.as_string(),
+                    ponder_move.as_string()
+                ),
+                None => println!("bestmove {}", best_move.as_string()),
+            }
         }
 
         self.history.age_scores();
+        self.capture_history.age_scores();
+        self.cont_hists.iter_mut().for_each(|h| h.age_scores());
+
+        best_move
+    }
+
+    // The reply we expect to our best move, which is the second move of its PV.
+    // If the PV is too short, we fall back on the TT move of the position after our best move.
+    fn ponder_move(
+        board: &Board,
+        tt: &TranspositionTable,
+        best_move: Move,
+        best_pv: &[Move],
+    ) -> Option<Move> {
+        if best_pv.first() == Some(&best_move) {
+            if let Some(&mv) = best_pv.get(1) {
+                return Some(mv);
+            }
+        }
+
+        let mut new_board = board.clone();
+        if !new_board.simple_try_play(best_move) {
+            return None;
+        }
+
+        let tt_move = tt.probe(ZobristHash::complete(&new_board))?.mv;
+        let is_legal =
+            tt_move.is_pseudolegal(&new_board) && new_board.clone().simple_try_play(tt_move);
+        is_legal.then_some(tt_move)
+    }
+
+    fn aspiration_search(
+        &mut self,
+        board: &Board,
+        tt: &TranspositionTable,
+        depth: Depth,
+        prev_score: EvalScore,
+        stopwatch: Instant,
+        report_info: bool,
+    ) -> EvalScore {
+        const ASP_MIN_DEPTH: Depth = 4;
+        const ASP_WINDOW: EvalScore = 25;
+        const ASP_MAX_FAIL_HIGH_REDUCTION: Depth = 3;
+
+        // MATE SEARCH
+        // we only care whether a score beats the mate target, and mate distance pruning
+        // cuts off every line that is too long to do that
+        if let Some(target) = self.mate_target {
+            let score = self.negamax::<true, true>(board, tt, depth, 0, target - 1, INF, false);
+            self.root_moves.sort(self.pv_idx..self.root_moves.len());
+            return score;
+        }
+
+        if depth < ASP_MIN_DEPTH {
+            let score = self.negamax::<true, true>(board, tt, depth, 0, -INF, INF, false);
+            self.root_moves.sort(self.pv_idx..self.root_moves.len());
+            return score;
+        }
+
+        let mut delta = ASP_WINDOW;
+        let mut alpha = (prev_score - delta).max(-INF);
+        let mut beta = (prev_score + delta).min(INF);
+        let mut fail_high_reduction = 0;
+
+        loop {
+            let search_depth = depth - fail_high_reduction;
+            let score = self.negamax::<true, true>(board, tt, search_depth, 0, alpha, beta, false);
+            self.root_moves.sort(self.pv_idx..self.root_moves.len());
+
+            if stop_flag_is_set() {
+                return score;
+            }
+
+            if score <= alpha {
+                if report_info {
+                    self.report_search_info(
+                        tt,
+                        self.pv_idx,
+                        score,
+                        TTFlag::UPPER_BOUND,
+                        depth,
+                        stopwatch,
+                    );
+                }
+
+                // pull beta down as well, since our score is probably lower than we thought
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INF);
+                fail_high_reduction = 0;
+            } else if score >= beta {
+                if report_info {
+                    self.report_search_info(
+                        tt,
+                        self.pv_idx,
+                        score,
+                        TTFlag::LOWER_BOUND,
+                        depth,
+                        stopwatch,
+                    );
+                }
+
+                // fail highs tend to resolve fast, so we can save some time by searching them at a lower depth
+                beta = (score + delta).min(INF);
+                fail_high_reduction = (fail_high_reduction + 1)
+                    .min(ASP_MAX_FAIL_HIGH_REDUCTION)
+                    .min(depth - 1);
+            } else {
+                return score;
+            }
+
+            delta += delta / 2;
+        }
     }
 
     fn out_of_time(&self) -> bool {
-        if self.node_cnt % Self::TIMER_CHECK_FREQ == 0 {
-            if let Some(t) = self.timer {
-                return t.is_hard_expired();
+        if ponder_flag_is_set() {
+            return false;
+        }
+
+        if let Some(t) = &self.timer {
+            if self.node_cnt % t.check_freq() == 0 {
+                return t.is_hard_expired(self.node_cnt);
             }
         }
 
         false
     }
 
+    // cut nodes are non-PV nodes where we expect a fail high
+    #[allow(clippy::too_many_arguments)]
     fn negamax<const IS_ROOT: bool, const DO_NULL_MOVE: bool>(
         &mut self,
         board: &Board,
@@ -304,16 +670,23 @@ impl Searcher {
         ply: Ply,
         mut alpha: EvalScore,
         beta: EvalScore,
+        cut_node: bool,
     ) -> EvalScore {
         if IS_ROOT {
             self.seldepth = 0;
+        } else if stop_flag_is_set() || self.out_of_time() {
+            // checking on the way down as well means we never search more than a node past the hard limit
+            set_stop_flag();
+            return 0;
         }
 
         self.pv_table.set_length(ply);
 
         let old_alpha = alpha;
         let is_pv = beta != alpha + 1;
+        debug_assert!(!(is_pv && cut_node));
         let in_check = board.in_check();
+        let mate_search = self.mate_target.is_some();
 
         let is_drawn =
             self.zobrist_stack.twofold_repetition(board.halfmoves) || board.fifty_move_draw();
@@ -337,59 +710,260 @@ impl Searcher {
         };
 
         if depth == 0 || ply >= MAX_PLY {
-            return self.qsearch(board, tt, ply, alpha, beta);
+            return self.qsearch::<true>(board, tt, ply, alpha, beta);
         }
 
         self.seldepth = self.seldepth.max(ply);
 
+        // in singular searches we are searching the same position again without the TT move,
+        // so we can't use the TT entry or overwrite it with our results
+        let excluded_move = self.search_stack.at(ply).excluded_move;
+        let is_singular_search = !excluded_move.is_null();
+
         // PROBE TT
         let hash = self.zobrist_stack.current_hash();
-        let tt_move = if let Some(tt_entry) = tt.probe(hash) {
-            let tt_score = tt_entry.score_from_tt(ply);
+        let tt_entry = if is_singular_search {
+            None
+        } else {
+            tt.probe(hash)
+        };
+
+        let mut tt_move = if let Some(entry) = tt_entry {
+            let tt_score = entry.score_from_tt(ply);
 
-            if !is_pv && tt_entry.cutoff_is_possible(alpha, beta, depth) {
+            if !is_pv && entry.cutoff_is_possible(alpha, beta, depth) {
                 return tt_score;
             }
 
-            tt_entry.mv
+            entry.mv
         } else {
             Move::NULL
         };
 
-        let pruning_allowed = !is_pv && !in_check && alpha.abs() < MATE_THRESHOLD;
+        // INTERNAL ITERATIVE REDUCTIONS
+        // without a TT move our move ordering is likely to be bad, so we search this node
+        // at a lower depth and hope to get a TT move for next time.
+        const IIR_DEPTH: Depth = 4;
+        const IID_FALLBACK: bool = false; // use classic internal iterative deepening instead
+        const IID_REDUCTION: Depth = 2;
+        if !IS_ROOT
+            && !mate_search
+            && (is_pv || cut_node)
+            && tt_move.is_null()
+            && !is_singular_search
+            && depth >= IIR_DEPTH
+        {
+            if IID_FALLBACK {
+                self.negamax::<false, true>(
+                    board,
+                    tt,
+                    depth - IID_REDUCTION,
+                    ply,
+                    alpha,
+                    beta,
+                    cut_node,
+                );
+                tt_move = tt.probe(hash).map_or(Move::NULL, |entry| entry.mv);
+            } else {
+                depth -= 1;
+            }
+        }
+
+        // STATIC EVAL
+        // the TT stores the raw eval, and we correct it for what we know about this pawn structure
+        let raw_eval = if in_check || is_singular_search {
+            None
+        } else {
+            Some(
+                tt_entry
+                    .and_then(|entry| entry.static_eval())
+                    .unwrap_or_else(|| temp_eval(board)),
+            )
+        };
+        let static_eval = if is_singular_search {
+            self.search_stack.at(ply).static_eval
+        } else {
+            raw_eval.map(|eval| self.correction_history.correct(board, eval))
+        };
+        self.search_stack.at_mut(ply).static_eval = static_eval;
+        let improving = self.search_stack.improving(ply);
+
+        let pruning_allowed = !is_pv
+            && !in_check
+            && !is_singular_search
+            && !mate_search
+            && alpha.abs() < MATE_THRESHOLD;
 
         let d = i32::from(depth);
-        if pruning_allowed {
+        if let (true, Some(static_eval)) = (pruning_allowed, static_eval) {
+            // RAZORING
+            // if we are far below alpha, we only check if a capture can save us
+            const RAZORING_ENABLED: bool = true;
+            const RAZORING_DEPTH: Depth = 3;
+            const RAZORING_MARGIN: EvalScore = 250;
+            if RAZORING_ENABLED
+                && depth <= RAZORING_DEPTH
+                && static_eval + RAZORING_MARGIN * d < alpha
+            {
+                let score = self.qsearch::<false>(board, tt, ply, alpha, alpha + 1);
+                if score <= alpha {
+                    return score;
+                }
+            }
+
             // REVERSE FUTILITY PRUNING
             const RFP_DEPTH: Depth = 8;
             const RFP_MARGIN: EvalScore = 120;
 
-            let static_eval = temp_eval(board);
-            if depth <= RFP_DEPTH && static_eval >= (beta + RFP_MARGIN * d) {
+            let rfp_margin = RFP_MARGIN * (d - i32::from(improving));
+            if depth <= RFP_DEPTH && static_eval >= (beta + rfp_margin) {
                 return static_eval;
             }
 
             // NULL MOVE PRUNING
             const NMP_DEPTH: Depth = 3;
-            if DO_NULL_MOVE && depth >= NMP_DEPTH {
-                // TODO: add zugzwang check
-                let reduction = 3;
+            const NMP_BASE_REDUCTION: Depth = 3;
+            const NMP_DEPTH_DIVISOR: Depth = 3;
+            const NMP_EVAL_DIVISOR: EvalScore = 200;
+            const NMP_MAX_EVAL_REDUCTION: EvalScore = 3;
+            const NMP_VERIFICATION_DEPTH: Depth = 12;
+            let stm = board.stm.as_index();
+            if DO_NULL_MOVE
+                && depth >= NMP_DEPTH
+                && static_eval >= beta
+                && ply >= self.nmp_min_ply[stm]
+                && board.has_non_pawn_material()
+            {
+                let eval_reduction =
+                    ((static_eval - beta) / NMP_EVAL_DIVISOR).min(NMP_MAX_EVAL_REDUCTION);
+                let reduction =
+                    NMP_BASE_REDUCTION + depth / NMP_DEPTH_DIVISOR + eval_reduction as Depth;
+                let nmp_depth = depth.saturating_sub(reduction);
 
                 let mut nmp_board = board.clone();
                 nmp_board.play_nullmove(&mut self.zobrist_stack);
+                self.search_stack.at_mut(ply).played = None;
                 let null_move_score = -self.negamax::<false, false>(
                     &nmp_board,
                     tt,
-                    depth.saturating_sub(reduction),
+                    nmp_depth,
                     ply + 1,
                     -beta,
                     -beta + 1,
+                    !cut_node,
                 );
 
                 self.zobrist_stack.pop();
 
                 if null_move_score >= beta {
-                    return null_move_score;
+                    // a null move can't prove a mate
+                    let score = if null_move_score >= MATE_THRESHOLD {
+                        beta
+                    } else {
+                        null_move_score
+                    };
+
+                    if depth < NMP_VERIFICATION_DEPTH || self.nmp_min_ply[stm] != 0 {
+                        return score;
+                    }
+
+                    // VERIFICATION SEARCH
+                    // at high depth we make sure we aren't in zugzwang by searching the position
+                    // at the reduced depth without null moves for our side
+                    self.nmp_min_ply[stm] = ply + nmp_depth - nmp_depth / 4;
+                    let verification_score = self.negamax::<false, true>(
+                        board,
+                        tt,
+                        nmp_depth,
+                        ply,
+                        beta - 1,
+                        beta,
+                        false,
+                    );
+                    self.nmp_min_ply[stm] = 0;
+
+                    if verification_score >= beta {
+                        return score;
+                    }
+                }
+            }
+
+            // PROBCUT
+            // a good capture that beats beta by a margin in a reduced search
+            // will very likely beat beta in a full depth search too
+            const PROBCUT_ENABLED: bool = true;
+            const PROBCUT_DEPTH: Depth = 5;
+            const PROBCUT_MARGIN: EvalScore = 200;
+            const PROBCUT_REDUCTION: Depth = 4;
+            let probcut_beta = beta + PROBCUT_MARGIN;
+            let tt_refutes_probcut = tt_entry.is_some_and(|entry| {
+                entry.depth() + PROBCUT_REDUCTION > depth && entry.score_from_tt(ply) < probcut_beta
+            });
+            if PROBCUT_ENABLED
+                && depth >= PROBCUT_DEPTH
+                && beta.abs() < MATE_THRESHOLD
+                && !tt_refutes_probcut
+            {
+                let see_threshold = probcut_beta - static_eval;
+                let mut probcut_picker = MovePicker::new();
+
+                while let Some(mv) = probcut_picker.pick::<false>(
+                    board,
+                    &self.node_history(board, ply),
+                    tt_move,
+                    [Move::NULL; 2],
+                    Move::NULL,
+                ) {
+                    if mv.is_quiet() || !board.search_see(mv, see_threshold) {
+                        continue;
+                    }
+
+                    let mut new_board = board.clone();
+                    if !new_board.try_play_move(mv, &mut self.zobrist_stack) {
+                        continue;
+                    }
+                    self.node_cnt += 1;
+                    self.search_stack.at_mut(ply).played = Some(PieceTo::new(board, mv));
+
+                    // verify with a cheap qsearch first, and only do the reduced search if that holds
+                    let mut score = -self.qsearch::<false>(
+                        &new_board,
+                        tt,
+                        ply + 1,
+                        -probcut_beta,
+                        -probcut_beta + 1,
+                    );
+                    if score >= probcut_beta {
+                        score = -self.negamax::<false, true>(
+                            &new_board,
+                            tt,
+                            depth - PROBCUT_REDUCTION,
+                            ply + 1,
+                            -probcut_beta,
+                            -probcut_beta + 1,
+                            !cut_node,
+                        );
+                    }
+
+                    self.zobrist_stack.pop();
+
+                    if stop_flag_is_set() || self.out_of_time() {
+                        set_stop_flag();
+                        return 0;
+                    }
+
+                    if score >= probcut_beta {
+                        tt.store(
+                            TTFlag::LOWER_BOUND,
+                            score,
+                            raw_eval,
+                            hash,
+                            ply,
+                            depth - PROBCUT_REDUCTION + 1,
+                            mv,
+                        );
+                        return score;
+                    }
                 }
             }
         }
@@ -398,12 +972,155 @@ impl Searcher {
         let mut best_move = Move::NULL;
         let mut moves_played = 0;
 
+        let counter = self.prev_moves(ply)[0].map_or(Move::NULL, |prev| {
+            self.counter_moves.counter(board.stm, prev)
+        });
+
+        let tt_move_is_noisy = !tt_move.is_null() && !tt_move.is_quiet();
+
         let mut move_picker = MovePicker::new();
+        move_picker.exclude(excluded_move);
         let mut played_quiets: ArrayVec<Move, { MovePicker::SIZE }> = ArrayVec::new();
+        let mut played_noisies: ArrayVec<Move, { MovePicker::SIZE }> = ArrayVec::new();
+
+        let mut root_move_idx = self.pv_idx;
+        loop {
+            // at the root we search the moves in the order of the last iteration
+            let next = if IS_ROOT {
+                root_move_idx += 1;
+                self.root_moves.get(root_move_idx - 1).map(|rm| rm.mv)
+            } else {
+                move_picker.pick::<true>(
+                    board,
+                    &self.node_history(board, ply),
+                    tt_move,
+                    self.killers.killers(ply),
+                    counter,
+                )
+            };
+            let Some(mv) = next else {
+                break;
+            };
+
+            let is_quiet = mv.is_quiet();
+            let gives_check = mv.gives_check(board);
+            let (quiet_hist_score, cont_hist_score) = if is_quiet {
+                let history = self.node_history(board, ply);
+                (
+                    history.quiet_score(board, mv),
+                    history.continuation_score(board, mv),
+                )
+            } else {
+                (0, 0)
+            };
+
+            // we can only prune once we have a move that doesn't get us mated
+            if !IS_ROOT && !mate_search && best_score > -MATE_THRESHOLD {
+                // LATE MOVE PRUNING
+                // quiets that have consistently failed as a follow-up to the previous moves only get half the budget
+                const LMP_DEPTH: Depth = 8;
+                const LMP_CONT_HIST_MARGIN: EvalScore = 2048;
+                let lmp_budget = (3 + d * d) / (2 - i32::from(improving));
+                let lmp_count = if cont_hist_score < -LMP_CONT_HIST_MARGIN * d {
+                    lmp_budget / 2
+                } else {
+                    lmp_budget
+                };
+                if is_quiet && depth <= LMP_DEPTH && moves_played >= lmp_count {
+                    // the quiets after this one can still have a better follow-up score
+                    if moves_played >= lmp_budget {
+                        move_picker.skip_quiets();
+                    }
+                    continue;
+                }
+
+                // FUTILITY PRUNING
+                // checks can swing the eval far more than a margin can account for, so we never prune them here
+                const FP_DEPTH: Depth = 8;
+                const FP_BASE: EvalScore = 100;
+                const FP_MARGIN: EvalScore = 75;
+                if let Some(eval) = static_eval {
+                    if is_quiet
+                        && !gives_check
+                        && depth <= FP_DEPTH
+                        && eval + FP_BASE + FP_MARGIN * d <= alpha
+                    {
+                        // no skip_quiets() here, the quiet checks after this move still need searching
+                        continue;
+                    }
+                }
+
+                // SEE PRUNING
+                const SEE_PRUNING_DEPTH: Depth = 8;
+                const SEE_QUIET_MARGIN: EvalScore = 60;
+                const SEE_NOISY_MARGIN: EvalScore = 25;
+                let see_threshold = if is_quiet {
+                    -SEE_QUIET_MARGIN * d
+                } else {
+                    -SEE_NOISY_MARGIN * d * d
+                };
+                if depth <= SEE_PRUNING_DEPTH && !board.search_see(mv, see_threshold) {
+                    continue;
+                }
+            }
+
+            // SINGULAR EXTENSIONS
+            // if the TT move beats every other move by a margin, we extend it
+            let mut extension = 0;
+            let double_extensions = self.search_stack.at(ply).double_extensions;
+            if let Some(entry) = tt_entry.filter(|_| !IS_ROOT && !mate_search && mv == tt_move) {
+                const SE_DEPTH: Depth = 8;
+                const SE_TT_DEPTH_MARGIN: Depth = 3;
+                const SE_DOUBLE_MARGIN: EvalScore = 20;
+                const SE_MAX_DOUBLE_EXTENSIONS: u8 = 6;
+
+                let tt_score = entry.score_from_tt(ply);
+                if depth >= SE_DEPTH
+                    && entry.depth() + SE_TT_DEPTH_MARGIN >= depth
+                    && entry.flag() != TTFlag::UPPER_BOUND
+                    && tt_score.abs() < MATE_THRESHOLD
+                {
+                    let singular_beta = tt_score - 2 * d;
+                    let singular_depth = (depth - 1) / 2;
+
+                    self.search_stack.at_mut(ply).excluded_move = mv;
+                    let singular_score = self.negamax::<false, true>(
+                        board,
+                        tt,
+                        singular_depth,
+                        ply,
+                        singular_beta - 1,
+                        singular_beta,
+                        cut_node,
+                    );
+                    self.search_stack.at_mut(ply).excluded_move = Move::NULL;
+
+                    if singular_score < singular_beta {
+                        extension = 1;
+
+                        // DOUBLE EXTENSIONS
+                        if !is_pv
+                            && singular_score < singular_beta - SE_DOUBLE_MARGIN
+                            && double_extensions < SE_MAX_DOUBLE_EXTENSIONS
+                        {
+                            extension = 2;
+                        }
+                    } else if singular_beta >= beta {
+                        // MULTICUT
+                        // multiple moves beat beta, so we assume this node will fail high
+                        return singular_beta;
+                    } else if tt_score >= beta {
+                        // NEGATIVE EXTENSIONS
+                        // the TT move isn't singular, so we can spend less time on it
+                        extension = -1;
+                    }
+                }
+            }
+
+            let new_depth = (d - 1 + extension) as Depth;
+            self.search_stack.at_mut(ply + 1).double_extensions =
+                double_extensions + u8::from(extension == 2);
 
-        while let Some(mv) =
-            move_picker.pick::<true>(board, &self.history, tt_move, self.killers.killer(ply))
-        {
             let mut new_board = board.clone();
 
             let is_legal = new_board.try_play_move(mv, &mut self.zobrist_stack);
@@ -412,33 +1129,63 @@ impl Searcher {
             }
 
             moves_played += 1;
+            let nodes_before = self.node_cnt;
+            let seldepth_before = self.seldepth;
+            if IS_ROOT {
+                // every root move reports how deep its own search went
+                self.seldepth = 0;
+            }
             self.node_cnt += 1;
+            self.search_stack.at_mut(ply).played = Some(PieceTo::new(board, mv));
 
             #[allow(unused_assignments)]
             // TODO: maybe refactor this later idk
             let mut score = 0;
             if moves_played == 1 {
-                score =
-                    -self.negamax::<false, true>(&new_board, tt, depth - 1, ply + 1, -beta, -alpha);
+                score = -self.negamax::<false, true>(
+                    &new_board,
+                    tt,
+                    new_depth,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    !is_pv && !cut_node,
+                );
             } else {
                 // LATE MOVE REDUCTIONS
                 const LMR_DEPTH: Depth = 3;
                 let lmr_threshold = if is_pv { 5 } else { 3 };
 
                 let mut do_full_depth_pvs = true;
-                if !in_check && depth >= LMR_DEPTH && moves_played > lmr_threshold {
-                    let mut r = get_lmr_reduction(depth, moves_played);
+                if !in_check && !mate_search && depth >= LMR_DEPTH && moves_played > lmr_threshold {
+                    const LMR_HISTORY_DIVISOR: EvalScore = 16384;
+                    let mut r = i32::from(get_lmr_reduction(depth, moves_played));
 
-                    if r > 1 {
+                    // quiets with a good history are more likely to be good moves
+                    if is_quiet {
+                        r -= quiet_hist_score / LMR_HISTORY_DIVISOR;
+                    }
+                    // we expect to fail high here anyway, so a cheaper search is good enough
+                    r += i32::from(cut_node);
+                    r += i32::from(!improving);
+                    // if the best move we know of is a capture, the other moves are unlikely to beat it
+                    r += i32::from(tt_move_is_noisy);
+                    r -= i32::from(gives_check);
+
+                    // never reduce below depth 1, and never extend
+                    let reduced_depth =
+                        (i32::from(new_depth) + 1 - r).clamp(1, i32::from(new_depth)) as Depth;
+
+                    if reduced_depth < new_depth {
                         // REDUCED PVS
-                        r = r.min(depth - 1); // dont reduce beyond (depth - r) == 1
                         score = -self.negamax::<false, true>(
                             &new_board,
                             tt,
-                            depth - r,
+                            reduced_depth,
                             ply + 1,
                             -alpha - 1,
                             -alpha,
+                            true,
                         );
                         // we want to try again without reductions if we beat alpha
                         do_full_depth_pvs = score > alpha && score < beta; // we want to try again without reductions if we beat alpha
@@ -450,10 +1197,11 @@ impl Searcher {
                     score = -self.negamax::<false, true>(
                         &new_board,
                         tt,
-                        depth - 1,
+                        new_depth,
                         ply + 1,
                         -alpha - 1,
                         -alpha,
+                        !cut_node,
                     );
 
                     // if our null-window search beat alpha without failing high, that means we might have a better move and need to re search with full window
@@ -461,25 +1209,51 @@ impl Searcher {
                         score = -self.negamax::<false, true>(
                             &new_board,
                             tt,
-                            depth - 1,
+                            new_depth,
                             ply + 1,
                             -beta,
                             -alpha,
+                            false,
                         );
                     }
                 }
             }
 
             self.zobrist_stack.pop();
+            let move_seldepth = self.seldepth;
+            self.seldepth = self.seldepth.max(seldepth_before);
+
+            if IS_ROOT {
+                if let Some(rm) = self.root_moves.find_mut(mv) {
+                    rm.nodes += self.node_cnt - nodes_before;
+                }
+            }
 
             if stop_flag_is_set() || self.out_of_time() {
                 set_stop_flag();
                 return 0;
             }
 
-            let is_quiet = mv.is_quiet();
+            if IS_ROOT {
+                let child_pv = self.pv_table.line(ply + 1);
+                if let Some(rm) = self.root_moves.find_mut(mv) {
+                    // only the first move and moves that raise alpha get an accurate score
+                    if moves_played == 1 || score > alpha {
+                        rm.score = score;
+                        rm.seldepth = move_seldepth;
+                        rm.pv.clear();
+                        rm.pv.push(mv);
+                        rm.pv.extend_from_slice(child_pv);
+                    } else {
+                        rm.score = -INF;
+                    }
+                }
+            }
+
             if is_quiet {
                 played_quiets.push(mv);
+            } else {
+                played_noisies.push(mv);
             }
 
             if score > best_score {
@@ -495,14 +1269,24 @@ impl Searcher {
                 if score >= beta {
                     if is_quiet {
                         self.killers.update(mv, ply);
-                        self.history.update(board, played_quiets.as_slice(), depth);
+                        if let Some(prev) = self.prev_moves(ply)[0] {
+                            self.counter_moves.update(board.stm, prev, mv);
+                        }
+                        self.update_quiet_histories(board, ply, played_quiets.as_slice(), depth);
                     }
+                    self.capture_history
+                        .update(board, played_noisies.as_slice(), mv, depth);
                     break;
                 }
             }
         }
 
         if moves_played == 0 {
+            // the excluded move was our only legal move, which isn't something to extend for
+            if is_singular_search {
+                return alpha;
+            }
+
             // either checkmate or stalemate
             return if in_check {
                 -EVAL_MAX + i32::from(ply)
@@ -511,12 +1295,31 @@ impl Searcher {
             };
         }
 
+        if is_singular_search {
+            return best_score;
+        }
+
         let tt_flag = TTFlag::determine(best_score, old_alpha, alpha, beta);
-        tt.store(tt_flag, best_score, hash, ply, depth, best_move);
+        tt.store(tt_flag, best_score, raw_eval, hash, ply, depth, best_move);
+
+        // CORRECTION HISTORY
+        // we only learn from non-noisy nodes whose bound actually tells us something about the eval error
+        if let (Some(raw_eval), Some(static_eval)) = (raw_eval, static_eval) {
+            let best_is_noisy = !best_move.is_null() && !best_move.is_quiet();
+            let bound_is_useful = !(tt_flag == TTFlag::LOWER_BOUND && best_score <= static_eval
+                || tt_flag == TTFlag::UPPER_BOUND && best_score >= static_eval);
+
+            if !best_is_noisy && bound_is_useful && best_score.abs() < MATE_THRESHOLD {
+                self.correction_history
+                    .update(board, depth, best_score, raw_eval);
+            }
+        }
+
         best_score
     }
 
-    fn qsearch(
+    // QUIET_CHECKS is set on the first ply of qsearch, where we also search quiet moves that give check
+    fn qsearch<const QUIET_CHECKS: bool>(
         &mut self,
         board: &Board,
         tt: &TranspositionTable,
@@ -524,42 +1327,116 @@ impl Searcher {
         mut alpha: EvalScore,
         beta: EvalScore,
     ) -> EvalScore {
+        if stop_flag_is_set() || self.out_of_time() {
+            set_stop_flag();
+            return 0;
+        }
+
         self.seldepth = self.seldepth.max(ply);
-        let old_alpha = alpha;
 
-        let stand_pat = temp_eval(board);
-        if stand_pat >= beta {
-            return stand_pat;
+        if ply >= MAX_PLY {
+            return self.correction_history.correct(board, temp_eval(board));
         }
 
-        if stand_pat > alpha {
-            alpha = stand_pat;
-        }
+        let old_alpha = alpha;
+        let in_check = board.in_check();
 
         // TT CUTOFF
         let hash = self.zobrist_stack.current_hash();
-        if let Some(tt_entry) = tt.probe(hash) {
-            let tt_score = tt_entry.score_from_tt(ply);
+        let tt_entry = tt.probe(hash);
+        let tt_move = if let Some(entry) = tt_entry {
+            if entry.cutoff_is_possible(alpha, beta, 0) {
+                return entry.score_from_tt(ply);
+            }
 
-            if tt_entry.cutoff_is_possible(alpha, beta, 0) {
-                return tt_score;
+            entry.mv
+        } else {
+            Move::NULL
+        };
+
+        // STAND PAT
+        // when in check we have to search every evasion, so we can't stand pat
+        let raw_eval = if in_check {
+            None
+        } else {
+            Some(
+                tt_entry
+                    .and_then(|entry| entry.static_eval())
+                    .unwrap_or_else(|| temp_eval(board)),
+            )
+        };
+        let static_eval = raw_eval.map(|eval| self.correction_history.correct(board, eval));
+
+        let mut best_score = -INF;
+        if let Some(stand_pat) = static_eval {
+            if stand_pat >= beta {
+                return stand_pat;
             }
+
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
         }
 
+        // a quiet TT move only gets searched as an evasion, or with the other quiet checks
+        let tt_move = if in_check || !tt_move.is_quiet() {
+            tt_move
+        } else {
+            Move::NULL
+        };
+
         let mut generator = MovePicker::new();
+        if QUIET_CHECKS {
+            generator.include_quiet_checks();
+        }
 
-        let mut best_score = stand_pat;
         let mut best_move = Move::NULL;
-        while let Some(mv) = generator.simple_pick::<false>(board) {
+        let mut moves_played = 0;
+        loop {
+            let history = self.node_history(board, ply);
+            let next = if in_check {
+                generator.pick::<true>(board, &history, tt_move, [Move::NULL; 2], Move::NULL)
+            } else {
+                generator.pick::<false>(board, &history, tt_move, [Move::NULL; 2], Move::NULL)
+            };
+            let Some(mv) = next else {
+                break;
+            };
+
+            if let Some(stand_pat) = static_eval {
+                // DELTA PRUNING
+                // even winning the captured piece for free won't get us close to alpha.
+                // quiet checks don't win material, they are here for the tactics they start
+                const DELTA_MARGIN: EvalScore = 200;
+                let captured_value = if mv.flag() == Flag::EP {
+                    SEE_VALS[Piece::PAWN.as_index()]
+                } else {
+                    SEE_VALS[board.piece_on_sq(mv.to()).as_index()]
+                };
+                if !mv.is_quiet()
+                    && !mv.is_promo()
+                    && stand_pat + captured_value + DELTA_MARGIN <= alpha
+                {
+                    continue;
+                }
+
+                // SEE PRUNING
+                // the same margins as in the main search at depth 0
+                if !board.search_see(mv, 0) {
+                    continue;
+                }
+            }
+
             let mut next_board = board.clone();
             let is_legal = next_board.try_play_move(mv, &mut self.zobrist_stack);
             if !is_legal {
                 continue;
             }
 
+            moves_played += 1;
             self.node_cnt += 1;
+            self.search_stack.at_mut(ply).played = Some(PieceTo::new(board, mv));
 
-            let score = -self.qsearch(&next_board, tt, ply + 1, -beta, -alpha);
+            let score = -self.qsearch::<false>(&next_board, tt, ply + 1, -beta, -alpha);
 
             self.zobrist_stack.pop();
 
@@ -582,8 +1459,250 @@ impl Searcher {
             }
         }
 
+        // no evasions means we are checkmated
+        if in_check && moves_played == 0 {
+            return -EVAL_MAX + i32::from(ply);
+        }
+
         let tt_flag = TTFlag::determine(best_score, old_alpha, alpha, beta);
-        tt.store(tt_flag, best_score, hash, ply, 0, best_move);
+        tt.store(tt_flag, best_score, raw_eval, hash, ply, 0, best_move);
         best_score
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+    use crate::{
+        move_generation::{
+            board_rep::{Board, Color, START_FEN},
+            chess_move::Move,
+            movegen::MovePicker,
+        },
+        search::{
+            constants::{EvalScore, Milliseconds, EVAL_MAX, INF, MAX_DEPTH},
+            search_timer::{MockClock, TimeAllocation},
+            zobrist_stack::ZobristStack,
+        },
+        uci::setoption::Overhead,
+    };
+
+    use super::{clear_stop_flag, SearchConfig, SearchLimit, SearchManager};
+
+    // every search shares the global stop flag, so only one test can search at a time
+    static SEARCH_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_search() -> MutexGuard<'static, ()> {
+        let guard = SEARCH_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        clear_stop_flag();
+        guard
+    }
+
+    struct TimeControl {
+        time: Milliseconds,
+        inc: Milliseconds,
+        moves_to_go: Option<u32>,
+    }
+
+    impl TimeControl {
+        const fn new(time: Milliseconds, inc: Milliseconds, moves_to_go: Option<u32>) -> Self {
+            Self {
+                time,
+                inc,
+                moves_to_go,
+            }
+        }
+    }
+
+    // A fixed cost per node, so longer time controls get deeper searches just like in a real game.
+    // This is far slower than a real search to keep the games cheap, but still fast enough
+    // that a node past the hard limit plus the latency fits inside the default Overhead.
+    const MICROS_PER_NODE: u128 = 5000;
+    const MOVES_PER_GAME: u32 = 45;
+    const OVERHEAD: Milliseconds = Overhead::DEFAULT as Milliseconds;
+    // time lost between us sending a move and the clock being stopped
+    const LATENCY: Milliseconds = 10;
+    // we look at the clock in every node, but leave a few nodes of room for where exactly we stop
+    const CHECK_SLACK_NODES: u128 = 32;
+    // node tm can never scale the soft limit down further than this
+    const MIN_SOFT_SCALE_PERCENT: u128 = 67;
+
+    // We play white against a cheap fixed depth opponent, and pretend that every node
+    // we search costs a fixed amount of time on our clock.
+    fn simulate_game(tc: &TimeControl) {
+        let mut search_manager = SearchManager::new();
+        search_manager.set_clock(Arc::new(MockClock::new(MICROS_PER_NODE)));
+
+        let mut board = Board::from_fen(START_FEN);
+        let mut zobrist_stack = ZobristStack::new(&board);
+        let mut clock = tc.time;
+
+        for move_num in 0..MOVES_PER_GAME {
+            let moves_to_go = tc.moves_to_go.map(|mtg| mtg - move_num % mtg);
+
+            let mut config = SearchConfig::new(OVERHEAD);
+            config.time[Color::White.as_index()] = clock;
+            config.inc[Color::White.as_index()] = tc.inc;
+            config.moves_to_go = moves_to_go;
+            config.limits.push(SearchLimit::Standard);
+
+            search_manager.update_state(&board, &zobrist_stack);
+            clear_stop_flag();
+            let mv = search_manager
+                .searcher
+                .go(&board, &search_manager.tt, &config, false);
+            search_manager.tt.age_table();
+
+            let alloc = TimeAllocation::new(clock, tc.inc, moves_to_go, OVERHEAD);
+            let used_micros = u128::from(search_manager.searcher.node_cnt) * MICROS_PER_NODE;
+            let context = format!(
+                "{}+{} {:?}: move {move_num}",
+                tc.time, tc.inc, tc.moves_to_go
+            );
+
+            assert!(
+                used_micros <= alloc.hard * 1000 + MICROS_PER_NODE * CHECK_SLACK_NODES,
+                "{context} used {used_micros}us with hard limit {}ms",
+                alloc.hard
+            );
+            // positions that are solved before MAX_DEPTH (like forced draws) can stop early
+            let search_exhausted = search_manager.searcher.completed_depth + 1 >= MAX_DEPTH;
+            assert!(
+                search_exhausted || used_micros * 100 >= alloc.soft * 1000 * MIN_SOFT_SCALE_PERCENT,
+                "{context} used {used_micros}us with soft limit {}ms",
+                alloc.soft
+            );
+
+            let charged = used_micros.div_ceil(1000) + LATENCY;
+            assert!(
+                charged < clock,
+                "{context} lost on time: needed {charged}ms with {clock}ms left"
+            );
+
+            clock = clock - charged + tc.inc;
+            if let Some(mtg) = tc.moves_to_go {
+                if (move_num + 1) % mtg == 0 {
+                    clock += tc.time;
+                }
+            }
+
+            assert!(board.try_play_move(mv, &mut zobrist_stack));
+            if MovePicker::first_legal_mv(&board).is_none() {
+                return;
+            }
+
+            let mut reply_config = SearchConfig::new(OVERHEAD);
+            reply_config.limits.push(SearchLimit::Depth(1));
+
+            search_manager.update_state(&board, &zobrist_stack);
+            clear_stop_flag();
+            let reply =
+                search_manager
+                    .searcher
+                    .go(&board, &search_manager.tt, &reply_config, false);
+
+            assert!(board.try_play_move(reply, &mut zobrist_stack));
+            if MovePicker::first_legal_mv(&board).is_none() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn never_flags() {
+        let _lock = lock_search();
+        let time_controls = [
+            TimeControl::new(60_000, 0, None),
+            TimeControl::new(60_000, 1000, None),
+            TimeControl::new(180_000, 2000, None),
+            TimeControl::new(60_000, 0, Some(10)),
+        ];
+
+        for tc in &time_controls {
+            simulate_game(tc);
+        }
+    }
+
+    #[test]
+    #[ignore = "takes too long"]
+    fn never_flags_at_long_time_controls() {
+        let _lock = lock_search();
+        let time_controls = [
+            TimeControl::new(900_000, 10_000, None),
+            TimeControl::new(5_400_000, 30_000, None),
+            TimeControl::new(7_200_000, 0, Some(40)),
+            TimeControl::new(2_400_000, 1000, Some(40)),
+        ];
+
+        for tc in &time_controls {
+            simulate_game(tc);
+        }
+    }
+
+    // runs `go mate moves` and returns the move we play, the score of the best root move, and whether its mate was proven
+    fn search_mate(fen: &str, moves: u32) -> (Move, EvalScore, bool) {
+        let mut search_manager = SearchManager::new();
+        let board = Board::from_fen(fen);
+        search_manager.update_state(&board, &ZobristStack::new(&board));
+
+        let mut config = SearchConfig::new(0);
+        config.limits.push(SearchLimit::Mate(moves));
+        let mv = search_manager
+            .searcher
+            .go(&board, &search_manager.tt, &config, false);
+
+        let searcher = &search_manager.searcher;
+        let score = searcher.root_moves.best().unwrap().score;
+        (mv, score, searcher.mate_is_proven(&board))
+    }
+
+    #[test]
+    fn go_mate_finds_mate() {
+        let _lock = lock_search();
+        let (_, score, proven) = search_mate("4k3/8/Q7/4p2K/8/3p4/1R1p4/8 w - - 0 1", 3);
+        assert_eq!(score, EVAL_MAX - 5);
+        assert!(proven);
+    }
+
+    #[test]
+    fn go_mate_reports_no_mate() {
+        let _lock = lock_search();
+        let (mv, score, proven) = search_mate(START_FEN, 2);
+        assert!(score < EVAL_MAX - 3);
+        assert!(!proven);
+        assert_eq!(
+            Some(mv),
+            MovePicker::first_legal_mv(&Board::from_fen(START_FEN))
+        );
+    }
+
+    #[test]
+    fn qsearch_handles_check() {
+        let _lock = lock_search();
+        let mut search_manager = SearchManager::new();
+
+        // black is up material, so standing pat would look good, but it's checkmate
+        let board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/nn4K1 b - - 0 1");
+        search_manager.update_state(&board, &ZobristStack::new(&board));
+        let score =
+            search_manager
+                .searcher
+                .qsearch::<false>(&board, &search_manager.tt, 0, -INF, INF);
+        assert_eq!(score, -EVAL_MAX);
+    }
+
+    #[test]
+    fn qsearch_finds_quiet_checks() {
+        let _lock = lock_search();
+        let mut search_manager = SearchManager::new();
+
+        // white is down a queen for a rook, and only the quiet Re8# gets back above alpha
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/q7/4R1K1 w - - 0 1");
+        search_manager.update_state(&board, &ZobristStack::new(&board));
+        let score = search_manager
+            .searcher
+            .qsearch::<true>(&board, &search_manager.tt, 0, 0, INF);
+        assert_eq!(score, EVAL_MAX - 1);
+    }
+}
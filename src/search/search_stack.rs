@@ -0,0 +1,64 @@
+use crate::{
+    move_generation::chess_move::Move,
+    search::{
+        constants::{EvalScore, Ply, MAX_PLY},
+        history::PieceTo,
+    },
+};
+
+#[derive(Debug, Copy, Clone)]
+pub struct StackEntry {
+    pub static_eval: Option<EvalScore>,
+    pub excluded_move: Move,
+    pub double_extensions: u8,
+    // the move made from this ply, None for null moves
+    pub played: Option<PieceTo>,
+}
+
+impl StackEntry {
+    const EMPTY: Self = Self {
+        static_eval: None,
+        excluded_move: Move::NULL,
+        double_extensions: 0,
+        played: None,
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchStack {
+    entries: [StackEntry; (MAX_PLY + 1) as usize],
+}
+
+impl SearchStack {
+    pub const fn new() -> Self {
+        Self {
+            entries: [StackEntry::EMPTY; (MAX_PLY + 1) as usize],
+        }
+    }
+
+    pub fn at(&self, ply: Ply) -> &StackEntry {
+        &self.entries[ply as usize]
+    }
+
+    pub fn at_mut(&mut self, ply: Ply) -> &mut StackEntry {
+        &mut self.entries[ply as usize]
+    }
+
+    // Our position is improving if our static eval is better than it was on our last move.
+    // If we were in check last move, we look one more move back, and assume we are improving if that fails too.
+    pub fn improving(&self, ply: Ply) -> bool {
+        let Some(eval) = self.at(ply).static_eval else {
+            return false;
+        };
+
+        let prev_eval = if ply >= 2 && self.at(ply - 2).static_eval.is_some() {
+            self.at(ply - 2).static_eval
+        } else if ply >= 4 {
+            self.at(ply - 4).static_eval
+        } else {
+            None
+        };
+
+        prev_eval.is_none_or(|prev| eval > prev)
+    }
+}
@@ -1,22 +1,250 @@
-use std::time::Instant;
+use std::{fmt::Debug, sync::Arc, time::Instant};
 
-use super::constants::Milliseconds;
+use super::constants::{Milliseconds, Nodes};
 
-#[derive(Debug, Copy, Clone)]
+pub trait Clock: Debug + Send + Sync {
+    // microseconds on this clock after `nodes` nodes have been searched, measured from an arbitrary starting point
+    fn micros(&self, nodes: Nodes) -> u128;
+
+    // how many nodes we can search between checks of this clock
+    fn check_freq(&self) -> Nodes {
+        1024
+    }
+}
+
+#[derive(Debug)]
+pub struct RealClock {
+    epoch: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl Clock for RealClock {
+    fn micros(&self, _nodes: Nodes) -> u128 {
+        self.epoch.elapsed().as_micros()
+    }
+}
+
+// Pretends that searching a fixed number of nodes takes one millisecond,
+// which makes timed searches reproducible regardless of machine load.
+#[derive(Debug)]
+pub struct NodeClock {
+    nodes_per_ms: u128,
+}
+
+impl NodeClock {
+    pub fn new(nodes_per_ms: u32) -> Self {
+        Self {
+            nodes_per_ms: u128::from(nodes_per_ms.max(1)),
+        }
+    }
+}
+
+impl Clock for NodeClock {
+    fn micros(&self, nodes: Nodes) -> u128 {
+        u128::from(nodes) * 1000 / self.nodes_per_ms
+    }
+
+    fn check_freq(&self) -> Nodes {
+        1
+    }
+}
+
+// Virtual clock that charges a fixed amount of time per node,
+// so time management can be tested without actually waiting.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    micros_per_node: u128,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub const fn new(micros_per_node: u128) -> Self {
+        Self { micros_per_node }
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn micros(&self, nodes: Nodes) -> u128 {
+        u128::from(nodes) * self.micros_per_node
+    }
+
+    fn check_freq(&self) -> Nodes {
+        1
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeAllocation {
+    pub hard: Milliseconds,
+    pub soft: Milliseconds,
+}
+
+impl TimeAllocation {
+    // how many moves we expect to play when there is no time control reset in sight
+    const SUDDEN_DEATH_MOVES: u32 = 25;
+    const HARD_SCALE: u128 = 5;
+
+    pub fn new(
+        time: Milliseconds,
+        inc: Milliseconds,
+        moves_to_go: Option<u32>,
+        overhead: Milliseconds,
+    ) -> Self {
+        let available = time.saturating_sub(overhead);
+
+        // in repeating time controls the clock gets refilled after moves_to_go moves,
+        // so we only need to split our time between the moves before the reset
+        let moves_left = moves_to_go
+            .map_or(Self::SUDDEN_DEATH_MOVES, |mtg| {
+                mtg.min(Self::SUDDEN_DEATH_MOVES)
+            })
+            .max(1);
+
+        let base = available / u128::from(moves_left);
+        let inc_bonus = inc * 3 / 4;
+
+        let mut soft = base + inc_bonus;
+        let mut hard = base * Self::HARD_SCALE + inc_bonus;
+
+        // even if we hit the hard limit on every move before the reset, this cap leaves
+        // a share of the clock for each of the remaining moves
+        if moves_to_go.is_some() {
+            hard = hard.min(available * 2 / (u128::from(moves_left) + 2));
+        }
+
+        // always leave a little time in the bank, even on the last move before the reset
+        let max_usage = available * 9 / 10;
+        hard = hard.min(max_usage);
+        soft = soft.min(hard);
+
+        Self { hard, soft }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SearchTimer {
-    timer: Instant,
+    clock: Arc<dyn Clock>,
+    start: u128,
     hard_limit: u128,
+    soft_limit: u128,
 }
 
 impl SearchTimer {
-    pub fn new(hard_limit: Milliseconds) -> Self {
+    const NODE_TM_BASE: f64 = 1.5;
+    const NODE_TM_SCALE: f64 = 1.35;
+
+    pub fn new(clock: &Arc<dyn Clock>, hard_limit: Milliseconds, soft_limit: Milliseconds) -> Self {
         Self {
-            timer: Instant::now(),
+            clock: Arc::clone(clock),
+            start: clock.micros(0),
             hard_limit: hard_limit.saturating_mul(1000),
+            soft_limit: soft_limit.min(hard_limit).saturating_mul(1000),
         }
     }
 
-    pub fn is_hard_expired(&self) -> bool {
-        (self.timer.elapsed().as_micros()) > self.hard_limit
+    pub fn check_freq(&self) -> Nodes {
+        self.clock.check_freq()
+    }
+
+    fn elapsed(&self, nodes: Nodes) -> u128 {
+        self.clock.micros(nodes).saturating_sub(self.start)
+    }
+
+    pub fn is_hard_expired(&self, nodes: Nodes) -> bool {
+        self.elapsed(nodes) > self.hard_limit
+    }
+
+    // The more effort we spend on the best move compared to the rest of the root moves,
+    // the more confident we are in it, so we can afford to stop searching earlier.
+    pub fn is_soft_expired(&self, best_move_nodes: Nodes, total_nodes: Nodes) -> bool {
+        let best_move_fraction = best_move_nodes as f64 / total_nodes.max(1) as f64;
+        let scale = (Self::NODE_TM_BASE - best_move_fraction) * Self::NODE_TM_SCALE;
+        let adjusted_limit = ((self.soft_limit as f64 * scale) as u128).min(self.hard_limit);
+
+        self.elapsed(total_nodes) > adjusted_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Clock, NodeClock, SearchTimer, TimeAllocation};
+
+    #[test]
+    fn sudden_death() {
+        let alloc = TimeAllocation::new(60_000, 0, None, 0);
+        assert_eq!(alloc.soft, 60_000 / 25);
+        assert_eq!(alloc.hard, 60_000 * 5 / 25);
+    }
+
+    #[test]
+    fn sudden_death_with_increment() {
+        let without_inc = TimeAllocation::new(60_000, 0, None, 0);
+        let with_inc = TimeAllocation::new(60_000, 1000, None, 0);
+        assert_eq!(with_inc.soft, without_inc.soft + 750);
+        assert_eq!(with_inc.hard, without_inc.hard + 750);
+
+        // a large increment can't make us spend time we don't have
+        let low_clock = TimeAllocation::new(500, 5000, None, 0);
+        assert!(low_clock.hard < 500);
+        assert!(low_clock.soft <= low_clock.hard);
+    }
+
+    #[test]
+    fn moves_in_minutes() {
+        // 40/120 with the full 40 moves to go has the same soft limit as sudden death,
+        // but can't risk as much on a single move
+        let alloc = TimeAllocation::new(120_000, 0, Some(40), 0);
+        let sudden_death = TimeAllocation::new(120_000, 0, None, 0);
+        assert_eq!(alloc.soft, sudden_death.soft);
+        assert_eq!(alloc.hard, 120_000 * 2 / 27);
+
+        // closer to the reset, we can afford more time per move
+        let alloc = TimeAllocation::new(30_000, 0, Some(10), 0);
+        assert_eq!(alloc.soft, 3000);
+        assert_eq!(alloc.hard, 5000);
+    }
+
+    #[test]
+    fn last_moves_before_reset() {
+        let alloc = TimeAllocation::new(10_000, 0, Some(2), 0);
+        assert_eq!(alloc.soft, 5000);
+        assert_eq!(alloc.hard, 5000);
+
+        let alloc = TimeAllocation::new(9000, 0, Some(1), 0);
+        assert_eq!(alloc.soft, 6000);
+        assert_eq!(alloc.hard, 6000);
+    }
+
+    #[test]
+    fn overhead_is_respected() {
+        let alloc = TimeAllocation::new(1000, 0, Some(1), 100);
+        assert_eq!(alloc.hard, 600);
+
+        let alloc = TimeAllocation::new(20, 0, None, 50);
+        assert_eq!(alloc, TimeAllocation { hard: 0, soft: 0 });
+    }
+
+    #[test]
+    fn node_clock_limits() {
+        let clock: Arc<dyn Clock> = Arc::new(NodeClock::new(4));
+        let timer = SearchTimer::new(&clock, 100, 50);
+
+        assert!(!timer.is_hard_expired(400));
+        assert!(timer.is_hard_expired(401));
+
+        // when all the effort went into the best move, node tm cuts the soft limit to 0.675x
+        assert!(!timer.is_soft_expired(135, 135));
+        assert!(timer.is_soft_expired(136, 136));
     }
 }
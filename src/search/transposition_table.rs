@@ -60,28 +60,42 @@ impl AgeAndFlag {
 pub struct TTEntry {
     pub mv: Move,             // 2 byte
     score: i16,               // 2 byte
-    key: u16,                 // 2 byte
+    static_eval: i16,         // 2 byte
     age_and_flag: AgeAndFlag, // 1 byte
     depth: Depth,             // 1 byte
 }
 
 impl TTEntry {
-    const BYTES: usize = 8;
+    const NO_EVAL: i16 = i16::MIN;
 
-    const fn new(age: u8, flag: TTFlag, depth: Depth, mv: Move, score: i16, key: u16) -> Self {
+    const fn new(
+        age: u8,
+        flag: TTFlag,
+        depth: Depth,
+        mv: Move,
+        score: i16,
+        static_eval: i16,
+    ) -> Self {
         let age_and_flag = AgeAndFlag::new(age, flag);
         Self {
             mv,
             score,
-            key,
+            static_eval,
             age_and_flag,
             depth,
         }
     }
 
-    fn key_from_hash(hash: ZobristHash) -> u16 {
-        // use upper 16 bits for key
-        (hash.as_u64() >> 48) as u16
+    fn eval_to_tt(static_eval: Option<EvalScore>) -> i16 {
+        static_eval.map_or(Self::NO_EVAL, |eval| eval as i16)
+    }
+
+    pub fn static_eval(self) -> Option<EvalScore> {
+        if self.static_eval == Self::NO_EVAL {
+            None
+        } else {
+            Some(EvalScore::from(self.static_eval))
+        }
     }
 
     fn score_to_tt(score: EvalScore, ply: Ply) -> i16 {
@@ -129,6 +143,10 @@ impl TTEntry {
         self.age_and_flag.flag()
     }
 
+    pub const fn depth(self) -> Depth {
+        self.depth
+    }
+
     #[allow(clippy::cast_sign_loss)]
     const fn quality(self) -> u16 {
         let age = self.age_and_flag.age() as u16;
@@ -151,9 +169,48 @@ impl From<TTEntry> for u64 {
     }
 }
 
+// Three entries share a cluster, with all of their 16 bit keys packed into a single word.
+// Each key is stored xor'd with its entry, so if another thread tears a write between
+// the two words the key check will fail and we just treat it as a miss.
+#[derive(Debug, Default)]
+struct TTCluster {
+    keys: AtomicU64,
+    entries: [AtomicU64; Self::ENTRIES],
+}
+
+impl TTCluster {
+    const BYTES: usize = 32;
+    const ENTRIES: usize = 3;
+
+    fn key_from_hash(hash: ZobristHash) -> u16 {
+        // use upper 16 bits for key
+        (hash.as_u64() >> 48) as u16
+    }
+
+    fn fold(data: u64) -> u16 {
+        (data ^ (data >> 16) ^ (data >> 32) ^ (data >> 48)) as u16
+    }
+
+    fn load(&self, idx: usize) -> (u16, TTEntry) {
+        let data = self.entries[idx].load(Ordering::Relaxed);
+        let keys = self.keys.load(Ordering::Relaxed);
+        let key = (keys >> (16 * idx)) as u16 ^ Self::fold(data);
+        (key, data.into())
+    }
+
+    fn save(&self, idx: usize, key: u16, entry: TTEntry) {
+        let data = u64::from(entry);
+        let shift = 16 * idx;
+        let keys = self.keys.load(Ordering::Relaxed) & !(0xFFFF << shift);
+        let key = u64::from(key ^ Self::fold(data)) << shift;
+        self.keys.store(keys | key, Ordering::Relaxed);
+        self.entries[idx].store(data, Ordering::Relaxed);
+    }
+}
+
 #[derive(Debug)]
 pub struct TranspositionTable {
-    table: Vec<AtomicU64>,
+    table: Vec<TTCluster>,
     age: u8,
 }
 
@@ -162,9 +219,9 @@ impl TranspositionTable {
         const BYTES_PER_MB: usize = 1024 * 1024;
 
         let bytes = megabytes * BYTES_PER_MB;
-        let entries = bytes / TTEntry::BYTES;
+        let clusters = bytes / TTCluster::BYTES;
         let mut table = vec![];
-        table.resize_with(entries, AtomicU64::default);
+        table.resize_with(clusters, TTCluster::default);
 
         Self { table, age: 0 }
     }
@@ -174,51 +231,73 @@ impl TranspositionTable {
         hash.as_usize() % self.table.len()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn store(
         &self,
         flag: TTFlag,
         best_score: EvalScore,
+        static_eval: Option<EvalScore>,
         hash: ZobristHash,
         ply: Ply,
         depth: Depth,
         best_move: Move,
     ) {
         let score = TTEntry::score_to_tt(best_score, ply);
-        let key = TTEntry::key_from_hash(hash);
-        let mut new_entry = TTEntry::new(self.age, flag, depth, best_move, score, key);
-
-        let index = self.index_from_hash(hash);
-        let old_entry: TTEntry = self.table[index].load(Ordering::Relaxed).into();
+        let eval = TTEntry::eval_to_tt(static_eval);
+        let key = TTCluster::key_from_hash(hash);
+        let mut new_entry = TTEntry::new(self.age, flag, depth, best_move, score, eval);
+
+        // clusters fill up in order, so an empty entry means this position isn't stored past it.
+        // otherwise overwrite this position if it is in the cluster, or the lowest quality entry
+        let cluster = &self.table[self.index_from_hash(hash)];
+        let mut replace = 0;
+        let mut replace_entry = cluster.load(0).1;
+        let mut same_position = false;
+        for idx in 0..TTCluster::ENTRIES {
+            let (old_key, old_entry) = cluster.load(idx);
+            if old_entry.flag() == TTFlag::UNINITIALIZED {
+                (replace, replace_entry) = (idx, old_entry);
+                break;
+            }
+            if old_key == key {
+                (replace, replace_entry, same_position) = (idx, old_entry, true);
+                break;
+            }
+            if old_entry.quality() < replace_entry.quality() {
+                (replace, replace_entry) = (idx, old_entry);
+            }
+        }
 
-        if new_entry.quality() >= old_entry.quality() {
-            if best_move.is_null() && new_entry.key == old_entry.key {
-                new_entry.mv = old_entry.mv;
+        if new_entry.quality() >= replace_entry.quality() {
+            if best_move.is_null() && same_position {
+                new_entry.mv = replace_entry.mv;
             }
-            self.table[index].store(new_entry.into(), Ordering::Relaxed);
+            cluster.save(replace, key, new_entry);
         }
     }
 
     pub fn probe(&self, hash: ZobristHash) -> Option<TTEntry> {
-        let index = self.index_from_hash(hash);
-        let key = TTEntry::key_from_hash(hash);
-        let entry = TTEntry::from(self.table[index].load(Ordering::Relaxed));
-        let flag = entry.age_and_flag.flag();
+        let key = TTCluster::key_from_hash(hash);
+        let cluster = &self.table[self.index_from_hash(hash)];
 
-        if (entry.key == key) && (flag != TTFlag::UNINITIALIZED) {
-            Some(entry)
-        } else {
-            None
-        }
+        (0..TTCluster::ENTRIES)
+            .map(|idx| cluster.load(idx))
+            .find(|&(k, entry)| k == key && entry.flag() != TTFlag::UNINITIALIZED)
+            .map(|(_, entry)| entry)
     }
 
     pub fn hashfull(&self) -> i32 {
         let mut hash_full = 0;
-        self.table.iter().take(1000).for_each(|x| {
-            let entry = TTEntry::from(x.load(Ordering::Relaxed));
-            if entry.age_and_flag.flag() != TTFlag::UNINITIALIZED {
-                hash_full += 1;
-            }
-        });
+        self.table
+            .iter()
+            .flat_map(|cluster| &cluster.entries)
+            .take(1000)
+            .for_each(|x| {
+                let entry = TTEntry::from(x.load(Ordering::Relaxed));
+                if entry.age_and_flag.flag() != TTFlag::UNINITIALIZED {
+                    hash_full += 1;
+                }
+            });
 
         hash_full
     }
@@ -230,10 +309,12 @@ impl TranspositionTable {
         if self.age == AGE_MAX {
             self.age = 0;
             self.table.iter_mut().for_each(|x| {
-                let mut entry = TTEntry::from(x.load(Ordering::Relaxed));
-                let flag = entry.age_and_flag.flag();
-                entry.age_and_flag = AgeAndFlag::new(0, flag);
-                x.store(entry.into(), Ordering::Relaxed);
+                for idx in 0..TTCluster::ENTRIES {
+                    let (key, mut entry) = x.load(idx);
+                    let flag = entry.age_and_flag.flag();
+                    entry.age_and_flag = AgeAndFlag::new(0, flag);
+                    x.save(idx, key, entry);
+                }
             });
         }
 
@@ -243,7 +324,7 @@ impl TranspositionTable {
     pub fn reset_entries(&mut self) {
         self.table
             .iter_mut()
-            .for_each(|x| *x = AtomicU64::default());
+            .for_each(|x| *x = TTCluster::default());
         self.age = 0;
     }
 }
@@ -266,10 +347,11 @@ mod tests {
         tt.age_table();
         let board = Board::from_fen(START_FEN);
         let best_score = 16;
+        let static_eval = -23;
         let flag = TTFlag::EXACT;
         let hash = ZobristHash::complete(&board);
         let mv = Move::from_str("d2d4", &board).unwrap();
-        tt.store(flag, best_score, hash, 4, 4, mv);
+        tt.store(flag, best_score, Some(static_eval), hash, 4, 4, mv);
 
         let entry = tt.probe(hash).unwrap();
         let expected = TTEntry::new(
@@ -278,9 +360,10 @@ mod tests {
             4,
             mv,
             best_score.try_into().unwrap(),
-            TTEntry::key_from_hash(hash),
+            static_eval.try_into().unwrap(),
         );
         assert_eq!(entry, expected);
+        assert_eq!(entry.static_eval(), Some(static_eval));
 
         let other_board =
             Board::from_fen("r3k2r/ppp2ppp/2n1bn2/8/2P1N3/1P4P1/P3PPBP/bNBR2K1 w kq - 0 12");
@@ -288,6 +371,24 @@ mod tests {
         assert_eq!(tt.probe(other_hash), None);
     }
 
+    #[test]
+    fn cluster_keeps_several_positions() {
+        let tt = TranspositionTable::new(16);
+        let board = Board::from_fen(START_FEN);
+        let mv = Move::from_str("e2e4", &board).unwrap();
+
+        // the same low bits put all of these in one cluster, the high bits tell them apart
+        let hashes = [1u64, 2, 3].map(|i| ZobristHash::new((i << 48) | 5));
+        for (depth, hash) in hashes.into_iter().enumerate() {
+            tt.store(TTFlag::EXACT, 10, None, hash, 0, depth as u8, mv);
+        }
+
+        for (depth, hash) in hashes.into_iter().enumerate() {
+            assert_eq!(tt.probe(hash).unwrap().depth(), depth as u8);
+        }
+        assert_eq!(tt.probe(ZobristHash::new((4 << 48) | 5)), None);
+    }
+
     #[test]
     fn flag_packing() {
         let age = 43;
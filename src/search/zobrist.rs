@@ -21,12 +21,16 @@ const KEYS: ZobristKeys = unsafe {
     )))
 };
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct ZobristHash(u64);
 
 impl ZobristHash {
     pub const EMPTY: Self = Self(0);
 
+    pub const fn new(data: u64) -> Self {
+        Self(data)
+    }
+
     pub fn hash_piece(&mut self, color: Color, piece: Piece, sq: Square) {
         self.0 ^= KEYS.pieces[color.as_index()][piece.as_index()][sq.as_index()];
     }
@@ -67,6 +71,19 @@ impl ZobristHash {
         hash
     }
 
+    // only covers pawn placement, so positions with the same pawn structure share a key
+    pub fn pawns(board: &Board) -> Self {
+        let mut hash = Self::EMPTY;
+
+        for color in Color::LIST {
+            bitloop!(|sq| board.piece_bb(Piece::PAWN, color), {
+                hash.hash_piece(color, Piece::PAWN, sq);
+            });
+        }
+
+        hash
+    }
+
     pub fn as_u64(self) -> u64 {
         self.0
     }
@@ -124,4 +141,22 @@ mod tests {
             assert_eq!(ZobristHash::complete(&board_a), ZobristHash::complete(&board_b), "Test {}", i + 1);
         }
     }
+
+    #[test]
+    fn pawn_hash_is_incremental() {
+        use super::*;
+
+        // en passant, promotion with capture and a normal pawn capture
+        let mut board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/7p/R3K1N1 w Qkq d6 0 1");
+        for mv in ["e5d6", "e8f7", "b7a8q", "h2g1q"] {
+            assert!(board.simple_try_play(Move::from_str(mv, &board).unwrap()));
+
+            assert_eq!(board.pawn_hash, ZobristHash::pawns(&board), "after {mv}");
+            assert_eq!(
+                board.pawn_hash,
+                Board::from_fen(&board.as_fen()).pawn_hash,
+                "after {mv}"
+            );
+        }
+    }
 }
@@ -30,8 +30,22 @@ macro_rules! option_string {
 new_option!(Overhead, 25, 1, 1000, "Overhead");
 new_option!(Hash, 32, 1, 8192, "Hash");
 new_option!(Threads, 1, 1, 128, "Threads");
+new_option!(NodesTime, 0, 0, 10000, "nodestime");
+new_option!(MultiPv, 1, 1, 256, "MultiPV");
+
+pub struct Ponder;
+
+impl Ponder {
+    pub const DEFAULT: bool = false;
+    pub const STR: &'static str = "Ponder";
+}
 
 pub fn display_options() {
-    let options = option_string!(Overhead, Hash, Threads);
+    let options = option_string!(Overhead, Hash, Threads, NodesTime, MultiPv);
+    println!(
+        "option name {} type check default {}",
+        Ponder::STR,
+        Ponder::DEFAULT
+    );
     println!("{options}");
 }
@@ -1,13 +1,14 @@
-use std::thread;
+use std::{sync::Arc, thread};
 
 use crate::{
     search::{
         constants::Milliseconds,
         search_manager::{self, SearchConfig, SearchLimit, SearchManager},
+        search_timer::{NodeClock, RealClock},
     },
     uci::{
         constants::{AUTHOR, NAME, VERSION},
-        setoption::{display_options, Overhead},
+        setoption::{display_options, MultiPv, Overhead},
         uci_input::{GoArg, UciCommand},
     },
 };
@@ -22,6 +23,7 @@ pub struct UciHandler {
 
     // options
     overhead: Milliseconds,
+    multi_pv: usize,
 }
 
 impl UciHandler {
@@ -30,6 +32,7 @@ impl UciHandler {
             search_manager: SearchManager::new(),
             stored_command: None,
             overhead: Milliseconds::from(Overhead::DEFAULT),
+            multi_pv: MultiPv::DEFAULT as usize,
         }
     }
 
@@ -46,9 +49,12 @@ impl UciHandler {
                 IsReady => println!("readyok"),
                 Quit => kill_program(),
                 Stop => {
+                    search_manager::clear_ponder_flag();
                     search_manager::set_stop_flag();
                     return None;
                 }
+                // the opponent played the move we were pondering on, so our time limits apply from now on
+                PonderHit => search_manager::clear_ponder_flag(),
                 _ => {
                     eprintln!("Cannot handle this command while searching");
                 }
@@ -81,7 +87,10 @@ impl UciHandler {
                 self.search_manager.update_state(&board, &zobrist_stack)
             }
             Go(args) => {
+                search_manager::clear_ponder_flag();
                 let mut config = SearchConfig::new(self.overhead);
+                config.multi_pv = self.multi_pv;
+                let mut use_pn_solver = false;
 
                 for arg in args {
                     match arg {
@@ -91,7 +100,11 @@ impl UciHandler {
                         GoArg::MoveTime(ms) => config.limits.push(SearchLimit::MoveTime(ms)),
                         GoArg::Nodes(nodes) => config.limits.push(SearchLimit::Nodes(nodes)),
                         GoArg::Depth(depth) => config.limits.push(SearchLimit::Depth(depth)),
+                        GoArg::Mate(moves) => config.limits.push(SearchLimit::Mate(moves)),
                         GoArg::Infinite => config.limits.push(SearchLimit::Infinite),
+                        GoArg::Ponder => search_manager::set_ponder_flag(),
+                        GoArg::PnSolver => use_pn_solver = true,
+                        GoArg::SearchMoves(moves) => config.search_moves = moves,
                         _ => eprintln!("Unrecognized Go Arg"),
                     }
                 }
@@ -102,17 +115,31 @@ impl UciHandler {
 
                 search_manager::clear_stop_flag();
 
+                let mate_moves = config.limits.iter().find_map(|&limit| match limit {
+                    SearchLimit::Mate(moves) => Some(moves),
+                    _ => None,
+                });
+
                 thread::scope(|s| {
-                    s.spawn(|| {
-                        self.search_manager.start_search(&config);
+                    s.spawn(|| match mate_moves.filter(|_| use_pn_solver) {
+                        Some(moves) => self.search_manager.start_mate_solver(moves),
+                        None => self.search_manager.start_search(&config),
                     });
 
                     self.stored_command = Self::respond_while_searching();
                 });
             }
             Stop => eprintln!("Uneeded Stop: Not Searching"),
+            PonderHit => eprintln!("Uneeded PonderHit: Not Pondering"),
+            // pondering only depends on whether we get a go ponder, so there is nothing to set up
+            SetOptionPonder(_) => (),
             SetOptionOverHead(time) => self.overhead = Milliseconds::from(time),
+            SetOptionMultiPv(lines) => self.multi_pv = lines as usize,
             SetOptionHash(megabytes) => self.search_manager.resize_tt(megabytes),
+            SetOptionNodesTime(0) => self.search_manager.set_clock(Arc::new(RealClock::new())),
+            SetOptionNodesTime(nodes_per_ms) => self
+                .search_manager
+                .set_clock(Arc::new(NodeClock::new(nodes_per_ms))),
             _ => eprintln!("Unrecognized Command"),
         };
     }
@@ -1,6 +1,6 @@
 use crate::{
     move_generation::{
-        board_rep::{Board, Color, START_FEN},
+        board_rep::{Board, Color, Square, START_FEN},
         chess_move::Move,
     },
     search::{
@@ -8,7 +8,7 @@ use crate::{
         zobrist_stack::ZobristStack,
     },
     uci::{
-        setoption::{Hash, Overhead, Threads},
+        setoption::{Hash, MultiPv, NodesTime, Overhead, Ponder, Threads},
         uci_handler::kill_program,
     },
 };
@@ -18,6 +18,7 @@ pub enum UciCommand {
     #[default]
     Unsupported,
     Stop,
+    PonderHit,
     Quit,
     Uci,
     IsReady,
@@ -29,9 +30,12 @@ pub enum UciCommand {
     SetOptionOverHead(u32),
     SetOptionHash(u32),
     SetOptionThreads(u32),
+    SetOptionNodesTime(u32),
+    SetOptionMultiPv(u32),
+    SetOptionPonder(bool),
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum GoArg {
     #[default]
     Unsupported,
@@ -41,7 +45,12 @@ pub enum GoArg {
     Nodes(Nodes),
     Depth(Depth),
     MovesToGo(u32),
+    Mate(u32),
+    // use the proof-number solver instead of our normal search, only together with Mate
+    PnSolver,
     Infinite,
+    Ponder,
+    SearchMoves(Vec<String>),
 }
 
 fn get_stdin() -> String {
@@ -65,6 +74,15 @@ fn expect_str(input: Option<&str>) -> Result<&str, ()> {
     }
 }
 
+// whether a token has the shape of a move in long algebraic notation, like e2e4 or a7a8q
+fn is_move_str(s: &str) -> bool {
+    (s.len() == 4 || s.len() == 5)
+        && s.is_char_boundary(2)
+        && s.is_char_boundary(4)
+        && Square::from_string(&s[0..2]).is_some()
+        && Square::from_string(&s[2..4]).is_some()
+}
+
 macro_rules! parse_nonzero {
     ($tokens:ident, $t:ty) => {{
         let v = $tokens.next().unwrap().parse::<$t>().unwrap_or(0);
@@ -85,6 +103,7 @@ impl UciCommand {
         let first = expect_str(tokens.next())?;
         match first {
             "stop" => res = UciCommand::Stop,
+            "ponderhit" => res = UciCommand::PonderHit,
             "quit" => res = UciCommand::Quit,
             "uci" => res = UciCommand::Uci,
             "isready" => res = UciCommand::IsReady,
@@ -118,6 +137,7 @@ impl UciCommand {
             }
             "go" => {
                 let mut arglist = vec![];
+                let mut tokens = tokens.peekable();
                 while let Some(arg) = tokens.next() {
                     let next_arg = match arg {
                         "wtime" => GoArg::Time(Color::White, parse_nonzero!(tokens, Milliseconds)?),
@@ -128,7 +148,20 @@ impl UciCommand {
                         "movestogo" => GoArg::MovesToGo(parse_nonzero!(tokens, u32)?),
                         "depth" => GoArg::Depth(parse_nonzero!(tokens, Depth)?),
                         "nodes" => GoArg::Nodes(parse_nonzero!(tokens, Nodes)?),
+                        "mate" => GoArg::Mate(parse_nonzero!(tokens, u32)?),
+                        "solver" => match expect_str(tokens.next())? {
+                            "pn" => GoArg::PnSolver,
+                            _ => GoArg::Unsupported,
+                        },
                         "infinite" => GoArg::Infinite,
+                        "ponder" => GoArg::Ponder,
+                        "searchmoves" => {
+                            let mut moves = vec![];
+                            while let Some(mv) = tokens.next_if(|s| is_move_str(s)) {
+                                moves.push(mv.to_owned());
+                            }
+                            GoArg::SearchMoves(moves)
+                        }
                         _ => GoArg::Unsupported,
                     };
 
@@ -152,6 +185,18 @@ impl UciCommand {
                     Threads::STR => UciCommand::SetOptionThreads(
                         parse_nonzero!(tokens, u32)?.clamp(Threads::MIN, Threads::MAX),
                     ),
+                    NodesTime::STR => UciCommand::SetOptionNodesTime(
+                        expect_str(tokens.next())?
+                            .parse::<u32>()
+                            .map_err(|_| ())?
+                            .clamp(NodesTime::MIN, NodesTime::MAX),
+                    ),
+                    MultiPv::STR => UciCommand::SetOptionMultiPv(
+                        parse_nonzero!(tokens, u32)?.clamp(MultiPv::MIN, MultiPv::MAX),
+                    ),
+                    Ponder::STR => UciCommand::SetOptionPonder(
+                        expect_str(tokens.next())?.parse::<bool>().map_err(|_| ())?,
+                    ),
                     _ => UciCommand::Unsupported,
                 };
             }
@@ -175,9 +220,15 @@ impl UciCommand {
 #[cfg(test)]
 mod tests {
     use crate::{
-        move_generation::{board_rep::Board, chess_move::Move},
+        move_generation::{
+            board_rep::{Board, Color},
+            chess_move::Move,
+        },
         search::zobrist_stack::ZobristStack,
-        uci::uci_input::UciCommand,
+        uci::{
+            setoption::MultiPv,
+            uci_input::{GoArg, UciCommand},
+        },
     };
 
     #[test]
@@ -201,4 +252,69 @@ mod tests {
             UciCommand::interpret_stdin(&uci).unwrap()
         );
     }
+
+    #[test]
+    fn go_searchmoves() {
+        let uci = "go searchmoves e2e4 d2d4 a7a8q depth 5";
+        let expected = UciCommand::Go(vec![
+            GoArg::SearchMoves(vec![
+                "e2e4".to_owned(),
+                "d2d4".to_owned(),
+                "a7a8q".to_owned(),
+            ]),
+            GoArg::Depth(5),
+        ]);
+
+        assert_eq!(expected, UciCommand::interpret_stdin(uci).unwrap());
+    }
+
+    #[test]
+    fn go_mate() {
+        let uci = "go mate 5";
+        assert_eq!(
+            UciCommand::Go(vec![GoArg::Mate(5)]),
+            UciCommand::interpret_stdin(uci).unwrap()
+        );
+
+        let uci = "go mate 7 solver pn";
+        assert_eq!(
+            UciCommand::Go(vec![GoArg::Mate(7), GoArg::PnSolver]),
+            UciCommand::interpret_stdin(uci).unwrap()
+        );
+    }
+
+    #[test]
+    fn go_ponder() {
+        let uci = "go ponder wtime 1000 btime 1000";
+        let expected = UciCommand::Go(vec![
+            GoArg::Ponder,
+            GoArg::Time(Color::White, 1000),
+            GoArg::Time(Color::Black, 1000),
+        ]);
+        assert_eq!(expected, UciCommand::interpret_stdin(uci).unwrap());
+
+        assert_eq!(
+            UciCommand::PonderHit,
+            UciCommand::interpret_stdin("ponderhit").unwrap()
+        );
+        assert_eq!(
+            UciCommand::SetOptionPonder(true),
+            UciCommand::interpret_stdin("setoption name Ponder value true").unwrap()
+        );
+    }
+
+    #[test]
+    fn setoption_multipv() {
+        let uci = "setoption name MultiPV value 4";
+        assert_eq!(
+            UciCommand::SetOptionMultiPv(4),
+            UciCommand::interpret_stdin(uci).unwrap()
+        );
+
+        let uci = "setoption name MultiPV value 1000";
+        assert_eq!(
+            UciCommand::SetOptionMultiPv(MultiPv::MAX),
+            UciCommand::interpret_stdin(uci).unwrap()
+        );
+    }
 }